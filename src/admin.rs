@@ -0,0 +1,425 @@
+use {
+    crate::{
+        bucket::RoutingSnapshot,
+        error::{Error, Result},
+        frame::{read_frame, read_message, write_message, DEFAULT_MAX_FRAME_SIZE},
+        key::Key,
+        node::Node,
+        rpc::Rpc,
+    },
+    async_std::{
+        future,
+        net::{TcpListener, TcpStream, ToSocketAddrs},
+        prelude::*,
+        sync::RwLock,
+        task,
+    },
+    ring::constant_time,
+    serde::{Deserialize, Serialize},
+    std::{
+        net::{IpAddr, SocketAddr},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    },
+};
+
+/// version of this node, reported in its status
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// upper bound of an admin response in bytes, larger than the one of RPCs since the key list
+/// grows with the number of stored values
+pub const ADMIN_MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// request to inspect a node, sent over its admin channel rather than between nodes
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub enum AdminRpc {
+    /// snapshot of the routing table
+    Routing,
+    /// key, size and remaining ttl of every stored value
+    Keys,
+    /// id, version, uptime and request counters of the node
+    Status,
+}
+
+/// admin rpc with the token authenticating a client which isn't on the loopback interface
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminRequest {
+    rpc: AdminRpc,
+    token: Option<String>,
+}
+
+impl AdminRequest {
+    pub fn new(rpc: AdminRpc, token: Option<String>) -> Self {
+        Self { rpc, token }
+    }
+
+    pub fn get_rpc(&self) -> &AdminRpc {
+        &self.rpc
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum AdminResponse {
+    Routing(RoutingSnapshot),
+    Keys(Vec<StoredKey>),
+    Status(Status),
+    /// request was refused or could not be read
+    Error(String),
+}
+
+/// value stored by a node, without the value itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredKey {
+    key: Key,
+    size: usize,
+    #[serde(with = "crate::config::seconds")]
+    ttl: Duration,
+}
+
+impl StoredKey {
+    pub fn get_key(&self) -> &Key {
+        &self.key
+    }
+
+    /// size of the value in bytes
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    /// time left before the value expires
+    pub fn get_ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+/// overview of a running node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    id: Key,
+    version: String,
+    #[serde(with = "crate::config::seconds")]
+    uptime: Duration,
+    contact_count: usize,
+    stored_count: usize,
+    counters: CounterValues,
+}
+
+impl Status {
+    /// current status of given node
+    pub fn of(node: &Node) -> Self {
+        Self {
+            id: node.get_id().clone(),
+            version: VERSION.to_owned(),
+            uptime: node.get_uptime(),
+            contact_count: node.contact_count(),
+            stored_count: node.stored_keys().len(),
+            counters: node.get_counters().values(),
+        }
+    }
+
+    pub fn get_id(&self) -> &Key {
+        &self.id
+    }
+
+    pub fn get_version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn get_uptime(&self) -> Duration {
+        self.uptime
+    }
+
+    pub fn get_contact_count(&self) -> usize {
+        self.contact_count
+    }
+
+    pub fn get_stored_count(&self) -> usize {
+        self.stored_count
+    }
+
+    pub fn get_counters(&self) -> &CounterValues {
+        &self.counters
+    }
+}
+
+/// number of requests handled by a node since it started, by rpc.
+/// counters are atomic, so that they are updated while holding the node for reading.
+#[derive(Debug, Default)]
+pub struct Counters {
+    ping: AtomicU64,
+    store: AtomicU64,
+    find_node: AtomicU64,
+    find_value: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl Counters {
+    /// count a request handled with given rpc
+    pub fn record(&self, rpc: &Rpc) {
+        let counter = match rpc {
+            Rpc::Ping => &self.ping,
+            Rpc::Store(..) => &self.store,
+            Rpc::FindNode(_) => &self.find_node,
+            Rpc::FindValue(_) => &self.find_value,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// count a request rejected because of an invalid signature
+    pub fn record_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// current value of every counter
+    pub fn values(&self) -> CounterValues {
+        CounterValues {
+            ping: self.ping.load(Ordering::Relaxed),
+            store: self.store.load(Ordering::Relaxed),
+            find_node: self.find_node.load(Ordering::Relaxed),
+            find_value: self.find_value.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// values of `Counters` at some point
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CounterValues {
+    ping: u64,
+    store: u64,
+    find_node: u64,
+    find_value: u64,
+    rejected: u64,
+}
+
+impl CounterValues {
+    pub fn get_ping(&self) -> u64 {
+        self.ping
+    }
+
+    pub fn get_store(&self) -> u64 {
+        self.store
+    }
+
+    pub fn get_find_node(&self) -> u64 {
+        self.find_node
+    }
+
+    pub fn get_find_value(&self) -> u64 {
+        self.find_value
+    }
+
+    pub fn get_rejected(&self) -> u64 {
+        self.rejected
+    }
+}
+
+/// accept admin connections on given listener and serve each of them in its own task.
+/// clients on the loopback interface are always served, others only if they send given token.
+/// without a token, the channel is restricted to the loopback interface.
+pub async fn serve_admin(
+    listener: TcpListener,
+    node: Arc<RwLock<Node>>,
+    token: Option<String>,
+) -> Result<()> {
+    let token = Arc::new(token);
+    let mut incoming = listener.incoming();
+    while let Some(Ok(stream)) = incoming.next().await {
+        let node = node.clone();
+        let token = token.clone();
+        task::spawn(async move {
+            if let Err(e) = connection_loop(stream, node, token).await {
+                println!("Admin connection fail: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// read admin requests from given stream and write a response frame to each.
+async fn connection_loop(
+    mut stream: TcpStream,
+    node: Arc<RwLock<Node>>,
+    token: Arc<Option<String>>,
+) -> Result<()> {
+    let peer = stream.peer_addr()?;
+    while let Some(frame) = read_frame(&mut stream, DEFAULT_MAX_FRAME_SIZE).await? {
+        let res = match serde_json::from_slice::<AdminRequest>(&frame) {
+            Ok(req) if is_authorized(&peer, req.token.as_deref(), token.as_deref()) => {
+                handle_admin_request(&node, req.get_rpc()).await
+            }
+            Ok(req) => {
+                println!("Admin request {:?} from {} rejected", req.get_rpc(), peer);
+                AdminResponse::Error("unauthorized".to_owned())
+            }
+            Err(e) => AdminResponse::Error(format!("invalid request: {}", e)),
+        };
+        write_message(&mut stream, &res, ADMIN_MAX_FRAME_SIZE).await?;
+    }
+    Ok(())
+}
+
+/// whether a client at given address sending given token may use the admin channel
+/// protected by `expected` token.
+fn is_authorized(peer: &SocketAddr, token: Option<&str>, expected: Option<&str>) -> bool {
+    let loopback = match peer.ip() {
+        IpAddr::V4(ip) => ip.is_loopback(),
+        // IPv4 loopback mapped to IPv6, i.e. ::ffff:127.0.0.0/104
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            ip.is_loopback()
+                || (segments[..6] == [0, 0, 0, 0, 0, 0xffff] && segments[6] >> 8 == 127)
+        }
+    };
+    if loopback {
+        return true;
+    }
+    match (token, expected) {
+        (Some(token), Some(expected)) => {
+            constant_time::verify_slices_are_equal(token.as_bytes(), expected.as_bytes()).is_ok()
+        }
+        _ => false,
+    }
+}
+
+/// build the response to given admin rpc
+pub async fn handle_admin_request(node: &RwLock<Node>, rpc: &AdminRpc) -> AdminResponse {
+    let node = node.read().await;
+    match rpc {
+        AdminRpc::Routing => AdminResponse::Routing(node.routing_snapshot()),
+        AdminRpc::Keys => AdminResponse::Keys(
+            node.stored_keys()
+                .into_iter()
+                .map(|(key, size, ttl)| StoredKey { key, size, ttl })
+                .collect(),
+        ),
+        AdminRpc::Status => AdminResponse::Status(Status::of(&node)),
+    }
+}
+
+/// send given request to the admin channel at given address and wait for the response.
+/// a refused request is returned as an error.
+pub async fn send_admin<A: ToSocketAddrs>(
+    addr: A,
+    req: &AdminRequest,
+    timeout: Duration,
+) -> Result<AdminResponse> {
+    let res = future::timeout(timeout, async {
+        let mut stream = TcpStream::connect(addr).await?;
+        write_message(&mut stream, req, DEFAULT_MAX_FRAME_SIZE).await?;
+        read_message(&mut stream, ADMIN_MAX_FRAME_SIZE)
+            .await?
+            .ok_or(Error::ConnectionClosed)
+    })
+    .await??;
+    match res {
+        AdminResponse::Error(msg) => Err(Error::AdminRefused(msg)),
+        res => Ok(res),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{request::Request, server::handle_request},
+        async_std::task::block_on,
+    };
+
+    fn create_node() -> Arc<RwLock<Node>> {
+        let host: SocketAddr = "127.0.0.1:2000".parse().unwrap();
+        Arc::new(RwLock::new(Node::new(host).unwrap()))
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let local: SocketAddr = "127.0.0.1:3000".parse().unwrap();
+        let local6: SocketAddr = "[::1]:3000".parse().unwrap();
+        let mapped: SocketAddr = "[::ffff:127.0.0.1]:3000".parse().unwrap();
+        let remote: SocketAddr = "192.0.2.1:3000".parse().unwrap();
+        for peer in [local, local6, mapped].iter() {
+            assert!(is_authorized(peer, None, None));
+            assert!(is_authorized(peer, None, Some("secret")));
+        }
+        for peer in [
+            "[::ffff:192.0.2.1]:3000",
+            "[::127.0.0.1]:3000",
+            "[::2]:3000",
+        ]
+        .iter()
+        {
+            assert!(!is_authorized(&peer.parse().unwrap(), None, None));
+        }
+        assert!(!is_authorized(&remote, None, None));
+        assert!(!is_authorized(&remote, Some("secret"), None));
+        assert!(!is_authorized(&remote, None, Some("secret")));
+        assert!(!is_authorized(&remote, Some("wrong"), Some("secret")));
+        assert!(is_authorized(&remote, Some("secret"), Some("secret")));
+    }
+
+    #[test]
+    fn test_handle_admin_request() {
+        let node = create_node();
+        let info = block_on(node.read()).get_info();
+        block_on(node.write())
+            .store("k1".into(), b"value".to_vec(), Duration::from_secs(60))
+            .unwrap();
+        block_on(handle_request(
+            &node,
+            &Request::new(None, Rpc::Ping, *info.get_host()),
+        ));
+
+        match block_on(handle_admin_request(&node, &AdminRpc::Status)) {
+            AdminResponse::Status(status) => {
+                assert_eq!(status.get_id(), info.get_id());
+                assert_eq!(status.get_version(), VERSION);
+                assert_eq!(status.get_stored_count(), 1);
+                assert_eq!(status.get_counters().get_ping(), 1);
+                assert_eq!(status.get_counters().get_store(), 0);
+            }
+            res => panic!("unexpected response {:?}", res),
+        }
+        match block_on(handle_admin_request(&node, &AdminRpc::Keys)) {
+            AdminResponse::Keys(keys) => {
+                assert_eq!(keys.len(), 1);
+                assert_eq!(keys[0].get_key(), &"k1".into());
+                assert_eq!(keys[0].get_size(), 5);
+                assert!(keys[0].get_ttl() <= Duration::from_secs(60));
+            }
+            res => panic!("unexpected response {:?}", res),
+        }
+        match block_on(handle_admin_request(&node, &AdminRpc::Routing)) {
+            AdminResponse::Routing(snapshot) => {
+                assert_eq!(snapshot.get_own_id(), info.get_id());
+                assert_eq!(snapshot.get_contact_count(), 0);
+            }
+            res => panic!("unexpected response {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_admin_over_tcp() {
+        block_on(async {
+            let node = create_node();
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            task::spawn(serve_admin(
+                listener,
+                node.clone(),
+                Some("secret".to_owned()),
+            ));
+
+            // loopback clients don't need the token
+            let req = AdminRequest::new(AdminRpc::Status, None);
+            match send_admin(addr, &req, Duration::from_secs(5)).await {
+                Ok(AdminResponse::Status(status)) => {
+                    assert_eq!(status.get_id(), node.read().await.get_id())
+                }
+                res => panic!("unexpected response {:?}", res),
+            }
+        });
+    }
+}
@@ -1,11 +1,14 @@
 use {
     clap::{App, Arg, ArgMatches, SubCommand},
     kadrs::{
+        admin::{send_admin, AdminRequest, AdminResponse, AdminRpc},
         error::{Error, Result},
-        request::Request,
+        frame::DEFAULT_MAX_FRAME_SIZE,
+        request::{Request, RPC_TIMEOUT},
         rpc::Rpc,
+        transport::{TcpTransport, Transport},
     },
-    std::net::SocketAddrV4,
+    std::{net::SocketAddr, time::Duration},
 };
 
 fn parse_method(matches: ArgMatches) -> Result<Rpc> {
@@ -26,18 +29,54 @@ fn parse_method(matches: ArgMatches) -> Result<Rpc> {
     if let Some(sub_match) = matches.subcommand_matches("store") {
         let key = sub_match.value_of("key")?;
         let value = sub_match.value_of("value")?;
-        return Ok(Rpc::Store(key.into(), value.into()));
+        let ttl = match sub_match.value_of("ttl") {
+            Some(s) => {
+                Some(Duration::from_secs(s.parse().map_err(|_| {
+                    Error::InvalidRequest(format!("invalid ttl: {}", s))
+                })?))
+            }
+            None => None,
+        };
+        return Ok(Rpc::Store(key.into(), value.into(), ttl));
     }
 
     Err(Error::InvalidRequest("no command matched".to_owned()))
 }
 
+/// admin rpc of the subcommand, None if it is a protocol rpc
+fn parse_admin_method(matches: &ArgMatches) -> Option<AdminRpc> {
+    match matches.subcommand_name() {
+        Some("routing") => Some(AdminRpc::Routing),
+        Some("keys") => Some(AdminRpc::Keys),
+        Some("status") => Some(AdminRpc::Status),
+        _ => None,
+    }
+}
+
+/// print the payload of given admin response as JSON, for other tools to read
+fn print_admin_response(res: &AdminResponse) -> Result<()> {
+    let json = match res {
+        AdminResponse::Routing(snapshot) => serde_json::to_string_pretty(snapshot)?,
+        AdminResponse::Keys(keys) => serde_json::to_string_pretty(keys)?,
+        AdminResponse::Status(status) => serde_json::to_string_pretty(status)?,
+        AdminResponse::Error(msg) => return Err(Error::AdminRefused(msg.clone())),
+    };
+    println!("{}", json);
+    Ok(())
+}
+
 #[async_std::main]
 async fn main() -> Result<()> {
     let app = App::new("kadrs-client")
         .version("0.1.0")
         .about("client app for kadrs")
         .arg(Arg::with_name("host").required(true))
+        .arg(
+            Arg::with_name("token")
+                .long("token")
+                .takes_value(true)
+                .help("token of the admin channel, needed outside of the loopback interface"),
+        )
         .subcommands(vec![
             SubCommand::with_name("ping").about("PING to check if node with given host is alive"),
             SubCommand::with_name("find_value")
@@ -51,20 +90,40 @@ async fn main() -> Result<()> {
                 .args(&vec![
                     Arg::with_name("key").required(true),
                     Arg::with_name("value").required(true),
+                    Arg::with_name("ttl")
+                        .long("ttl")
+                        .takes_value(true)
+                        .help("seconds until the value expires"),
                 ]),
+            SubCommand::with_name("routing")
+                .about("routing table of the node, host must be its admin address"),
+            SubCommand::with_name("keys").about(
+                "keys stored by the node with their size and ttl, host must be its admin address",
+            ),
+            SubCommand::with_name("status").about(
+                "version, uptime and request counters of the node, host must be its admin address",
+            ),
         ]);
 
     let matches = app.get_matches();
 
-    let host: SocketAddrV4 = match matches.value_of("host").unwrap().parse() {
+    let host: SocketAddr = match matches.value_of("host").unwrap().parse() {
         Ok(addr) => addr,
         Err(_) => panic!("Invalid host string"),
     };
 
+    if let Some(rpc) = parse_admin_method(&matches) {
+        let token = matches.value_of("token").map(str::to_owned);
+        let res = send_admin(host, &AdminRequest::new(rpc, token), RPC_TIMEOUT).await?;
+        return print_admin_response(&res);
+    }
+
     let rpc = parse_method(matches)?;
-    let req = Request::new(None, rpc, host.into());
+    let req = Request::new(None, rpc, host);
     println!("Request: {:?}", req);
-    let res = req.send().await?;
+    let res = TcpTransport::new(DEFAULT_MAX_FRAME_SIZE)
+        .send(&req, RPC_TIMEOUT)
+        .await?;
     println!("Response: {:?}", res);
     Ok(())
 }
@@ -0,0 +1,110 @@
+use {
+    crate::{
+        error::{Error, Result},
+        lookup::lookup_nodes,
+        node::{update_contact, Node},
+        request::Request,
+        response::ResponseBody,
+        rpc::Rpc,
+    },
+    async_std::{sync::RwLock, task},
+    std::{fs, net::SocketAddr, path::Path, sync::Arc, time::Duration},
+};
+
+/// join the network through given seeds.
+/// 1. ping every seed and insert the ones responding into our buckets.
+/// 2. look up our own id, which fills buckets close to us.
+/// 3. refresh every bucket of the routing table by looking up a random id in its range.
+///
+/// returns number of contacts in the routing table after joining.
+/// fails with `Error::NoSeedReachable` if none of the seeds responds.
+pub async fn bootstrap(node: &Arc<RwLock<Node>>, seeds: &[SocketAddr]) -> Result<usize> {
+    let (own_info, identity, timeout, transport) = {
+        let node = node.read().await;
+        (
+            node.get_info(),
+            node.get_identity(),
+            node.get_config().get_rpc_timeout(),
+            node.get_transport(),
+        )
+    };
+
+    let handles: Vec<_> = seeds
+        .iter()
+        .filter(|seed| !own_info.has_host(seed))
+        .map(|seed| {
+            let mut req = Request::new(Some(own_info.clone()), Rpc::Ping, *seed);
+            req.sign(&identity);
+            let transport = transport.clone();
+            task::spawn(async move { transport.send(&req, timeout).await })
+        })
+        .collect();
+    let mut reachable = 0;
+    for handle in handles {
+        match handle.await {
+            Ok(res) if matches!(res.get_body(), Some(ResponseBody::PONG)) => {
+                update_contact(node, res.get_from().clone()).await;
+                reachable += 1;
+            }
+            Ok(res) => println!("Unexpected response from seed: {:?}", res),
+            Err(e) => println!("Seed unreachable: {}", e),
+        }
+    }
+    if reachable == 0 {
+        return Err(Error::NoSeedReachable);
+    }
+
+    let own_id = own_info.get_id();
+    lookup_nodes(node, own_id).await?;
+
+    let targets = node.read().await.idle_buckets(Duration::from_secs(0));
+    for target in targets.iter() {
+        lookup_nodes(node, target).await?;
+    }
+
+    Ok(node.read().await.contact_count())
+}
+
+/// read seed addresses from given file, one `host:port` or `[host]:port` per line.
+/// empty lines and lines starting with `#` are ignored.
+pub fn read_seeds<P: AsRef<Path>>(path: P) -> Result<Vec<SocketAddr>> {
+    fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.parse().map_err(Into::into))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_read_seeds() {
+        let path = env::temp_dir().join(format!("kadrs_seeds_{}", std::process::id()));
+        fs::write(
+            &path,
+            "# seeds\n127.0.0.1:2000\n\n  127.0.0.1:2001  \n[::1]:2002\n",
+        )
+        .unwrap();
+        let seeds = read_seeds(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let expected: Vec<SocketAddr> = vec![
+            "127.0.0.1:2000".parse().unwrap(),
+            "127.0.0.1:2001".parse().unwrap(),
+            "[::1]:2002".parse().unwrap(),
+        ];
+        assert_eq!(seeds, expected);
+    }
+
+    #[test]
+    fn test_read_seeds_invalid_address() {
+        let path = env::temp_dir().join(format!("kadrs_invalid_seeds_{}", std::process::id()));
+        fs::write(&path, "not an address\n").unwrap();
+        let res = read_seeds(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(res, Err(Error::AddrParse(_))));
+    }
+}
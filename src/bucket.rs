@@ -4,100 +4,524 @@ use {
         key::Key,
         node::NodeInfo,
     },
-    arrayvec::ArrayVec,
-    std::{fmt, mem::MaybeUninit},
+    arrayvec::{ArrayVec, CapacityError},
+    serde::{Deserialize, Serialize},
+    std::{
+        fmt,
+        time::{Duration, Instant, SystemTime},
+    },
 };
 
-// TODO: use const generics if ready
-const K: usize = 10;
+/// default bucket size, set per node with `Config`
+pub const DEFAULT_K: usize = 20;
+
+/// number of candidates kept in the replacement cache of each bucket
+pub const REPLACEMENT_CACHE_SIZE: usize = 5;
+
+/// node in a bucket with the last time we heard from it, and the number of requests it failed
+/// to respond to since then
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    info: NodeInfo,
+    last_seen: SystemTime,
+    #[serde(default)]
+    failures: u32,
+}
+
+impl Contact {
+    /// contact seen just now
+    pub fn new(info: NodeInfo) -> Self {
+        Self {
+            info,
+            last_seen: SystemTime::now(),
+            failures: 0,
+        }
+    }
+
+    pub fn get_info(&self) -> &NodeInfo {
+        &self.info
+    }
+
+    pub fn get_last_seen(&self) -> SystemTime {
+        self.last_seen
+    }
+
+    pub fn get_failures(&self) -> u32 {
+        self.failures
+    }
+}
 
-/// let 0 <= i < 160, store k nodes info whose distance is 2^i <= d < 2^(i+1) far.
+/// store k nodes info whose id falls in the range of the bucket, i.e. ids whose first `depth`
+/// bits are the ones of `prefix`.
 /// bucket has at most k nodes
 /// when node received any message from other nodes, bucket manages nodes in the following rule
 /// 1. if node is already in the bucket, move it to the tail of the bucket.
 /// 2. if node is not in the bucket, and bucket is not full, append the node at the tail.
 /// 3. if node is not in the bucket, and bucket is full, ping the least-recently seen node which is
 ///    at the head of the bucket, if it doesn't respond, evict the least-recently seen node and push
-///    new node at the tail. if it does respond, discard new node.
+///    new node at the tail. if it does respond, keep new node in the replacement cache.
+///
+/// replacement cache holds recently seen nodes which didn't fit in the bucket, most recent at the
+/// tail. when a node is evicted or marked stale, the most recent replacement takes its place.
+///
+/// last_lookup records when a lookup for an id in the range of the bucket was last started, so
+/// that idle buckets can be refreshed.
 #[derive(Debug)]
 pub struct Bucket {
-    nodes: ArrayVec<[NodeInfo; K]>,
+    k: usize,
+    prefix: Key,
+    depth: u32,
+    nodes: Vec<Contact>,
+    replacements: ArrayVec<[NodeInfo; REPLACEMENT_CACHE_SIZE]>,
+    last_lookup: Instant,
 }
 
 impl Bucket {
-    pub fn new() -> Self {
+    /// empty bucket covering the whole id space, holding at most k nodes
+    pub fn new(k: usize) -> Self {
         Self {
-            nodes: ArrayVec::new(),
+            k,
+            prefix: Key::new([0; 20]),
+            depth: 0,
+            nodes: Vec::with_capacity(k),
+            replacements: ArrayVec::new(),
+            last_lookup: Instant::now(),
         }
     }
 
-    /// append given node to the tail of the bucket
+    pub fn get_contacts(&self) -> &[Contact] {
+        &self.nodes
+    }
+
+    pub fn contains(&self, node_info: &NodeInfo) -> bool {
+        self.position(node_info).is_some()
+    }
+
+    pub fn get_replacements(&self) -> &[NodeInfo] {
+        &self.replacements
+    }
+
+    pub fn get_last_lookup(&self) -> Instant {
+        self.last_lookup
+    }
+
+    /// first id in the range of this bucket
+    pub fn get_prefix(&self) -> &Key {
+        &self.prefix
+    }
+
+    /// number of leading bits shared by every id in the range of this bucket
+    pub fn get_depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// whether given id falls in the range of this bucket
+    pub fn covers(&self, id: &Key) -> bool {
+        self.prefix.distance(id).most_significant_bit() >= self.depth
+    }
+
+    /// random id in the range of this bucket
+    pub fn random_id(&self) -> Key {
+        self.prefix.random_with_prefix(self.depth)
+    }
+
+    /// split the range of this bucket in two halves, one whose next bit is 0 and one whose next
+    /// bit is 1, moving every node and replacement to the half covering it. replacements fill
+    /// the room left in each half.
+    /// panics if the bucket covers a single id
+    fn split(self) -> (Self, Self) {
+        let half = |prefix: Key| Self {
+            k: self.k,
+            prefix,
+            depth: self.depth + 1,
+            nodes: Vec::with_capacity(self.k),
+            replacements: ArrayVec::new(),
+            last_lookup: self.last_lookup,
+        };
+        let mut zero = half(self.prefix.clone());
+        let mut one = half(self.prefix.flip_bit(self.depth));
+        for contact in self.nodes {
+            if contact.info.get_id().bit(self.depth) {
+                one.nodes.push(contact);
+            } else {
+                zero.nodes.push(contact);
+            }
+        }
+        for node_info in self.replacements {
+            if node_info.get_id().bit(self.depth) {
+                one.replacements.push(node_info);
+            } else {
+                zero.replacements.push(node_info);
+            }
+        }
+        zero.promote_replacements();
+        one.promote_replacements();
+        (zero, one)
+    }
+
+    /// record a lookup in the range of this bucket
+    pub fn touch(&mut self) {
+        self.last_lookup = Instant::now();
+    }
+
+    /// append given node to the tail of the bucket, as seen just now, dropping it from the
+    /// replacement cache
     pub fn push_back(&mut self, node_info: NodeInfo) -> Result<()> {
-        self.nodes.try_push(node_info).map_err(Into::into)
+        if self.is_full() {
+            return Err(CapacityError::new(node_info).into());
+        }
+        self.replacements
+            .retain(|n| n.get_id() != node_info.get_id());
+        self.nodes.push(Contact::new(node_info));
+        Ok(())
+    }
+
+    fn is_full(&self) -> bool {
+        self.nodes.len() >= self.k
     }
 
     /// remove item at given index
     /// panics if index is out of bounds
     pub fn remove(&mut self, index: usize) -> NodeInfo {
-        self.nodes.remove(index)
+        self.nodes.remove(index).info
     }
 
-    /// move item at given index to tail of the bucket
+    /// move item at given index to tail of the bucket, as seen just now
     /// panics if given index is out of bounds
     pub fn move_to_tail(&mut self, index: usize) -> Result<()> {
         if index >= self.nodes.len() {
             return Err(Error::IndexOutOfBounds(index, self.nodes.len() - 1));
         }
 
-        let node = self.nodes.remove(index);
-        self.nodes.push(node);
+        let mut contact = self.nodes.remove(index);
+        contact.last_seen = SystemTime::now();
+        contact.failures = 0;
+        self.nodes.push(contact);
         Ok(())
     }
 
+    /// contacts are matched by id only, since a node keeps its id when its address changes
+    fn position(&self, node_info: &NodeInfo) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|c| c.info.get_id() == node_info.get_id())
+    }
+
+    /// push given node at the tail of the replacement cache, dropping the oldest one if full.
+    pub fn push_replacement(&mut self, node_info: NodeInfo) {
+        if let Some(index) = self
+            .replacements
+            .iter()
+            .position(|n| n.get_id() == node_info.get_id())
+        {
+            self.replacements.remove(index);
+        } else if self.replacements.is_full() {
+            self.replacements.remove(0);
+        }
+        self.replacements.push(node_info);
+    }
+
     /// update bucket with given node_info in rule specified above.
-    //TODO: PING is not implemented yet.
-    pub fn update(&mut self, node_info: NodeInfo) {
-        if let Some(index) = self.nodes.iter().position(|n| *n == node_info) {
+    /// when the bucket is full, new node is kept in the replacement cache and the least-recently
+    /// seen node is returned. caller must ping it and report the result with `ping_result`.
+    pub fn update(&mut self, node_info: NodeInfo) -> Option<NodeInfo> {
+        if let Some(index) = self.position(&node_info) {
+            // keep the address the node contacted us from
+            self.nodes[index].info = node_info;
             let _ = self.move_to_tail(index);
-        } else if !self.nodes.is_full() {
+            None
+        } else if !self.is_full() {
             let _ = self.push_back(node_info);
+            None
         } else {
-            // TODO: ping least-recently seen node which on the head and set it to tail if pong
-            // if it doesn't respond, evict it and push new node at the tail
-            let _ = self.move_to_tail(0);
+            self.push_replacement(node_info);
+            self.nodes.first().map(|c| c.info.clone())
+        }
+    }
+
+    /// apply result of the ping sent to least-recently seen node `head`.
+    /// if head responded, move it to the tail. otherwise evict it in favor of a replacement.
+    pub fn ping_result(&mut self, head: &NodeInfo, alive: bool) {
+        match self.position(head) {
+            Some(index) if alive => {
+                let _ = self.move_to_tail(index);
+            }
+            Some(index) => {
+                self.remove(index);
+                self.promote_replacement();
+            }
+            // head was removed while waiting for the ping
+            None => self.promote_replacement(),
+        }
+    }
+
+    /// replace given node with the most recent replacement.
+    /// the node is kept if there is no replacement, so the bucket never loses contacts
+    /// because of a transient failure. the failure is counted until we hear from it again.
+    pub fn mark_stale(&mut self, node_info: &NodeInfo) {
+        let index = match self.position(node_info) {
+            Some(index) => index,
+            None => return,
+        };
+        if self.replacements.is_empty() {
+            self.nodes[index].failures += 1;
+            return;
+        }
+        self.remove(index);
+        self.promote_replacement();
+    }
+
+    /// serializable copy of this bucket
+    pub fn snapshot(&self) -> BucketSnapshot {
+        BucketSnapshot {
+            prefix: self.prefix.clone(),
+            depth: self.depth,
+            contacts: self.nodes.clone(),
+            replacements: self.replacements.to_vec(),
+            since_lookup: self.last_lookup.elapsed(),
+        }
+    }
+
+    /// move the most recent replacement into the bucket if there is room.
+    fn promote_replacement(&mut self) {
+        if self.is_full() {
+            return;
+        }
+        if let Some(node_info) = self.replacements.pop() {
+            let _ = self.push_back(node_info);
+        }
+    }
+
+    /// move replacements into the bucket, most recent first, while there is room.
+    fn promote_replacements(&mut self) {
+        while !self.is_full() && !self.replacements.is_empty() {
+            self.promote_replacement();
         }
     }
 }
 
 impl fmt::Display for Bucket {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self.nodes)
+        write!(
+            f,
+            "nodes: {:?}, replacements: {:?}",
+            self.nodes, self.replacements
+        )
     }
 }
 
 /// kBucket implementation
-/// store k nodes in single bucket
+/// routing table as described in the paper: a single bucket covers the whole id space at first,
+/// and a full bucket is split in two halves only when its range covers our own id. buckets are
+/// ordered from the farthest range to the one covering our own id.
+///
+/// with relaxed splitting, a full bucket which doesn't cover our own id is split as well when
+/// the new node would be one of the k closest nodes to our own id, so that all of them are kept
+/// even when the ids around ours are unevenly distributed.
 pub struct KBucket {
-    buckets: [Bucket; 160],
+    own_id: Key,
+    k: usize,
+    relaxed: bool,
+    buckets: Vec<Bucket>,
 }
 
 impl KBucket {
-    pub fn new() -> Self {
-        let mut buckets: [Bucket; 160] = unsafe { MaybeUninit::uninit().assume_init() };
-        for i in buckets.iter_mut() {
-            *i = Bucket::new();
+    /// routing table of node with given id, made of a single empty bucket holding at most k nodes
+    pub fn new(own_id: Key, k: usize, relaxed: bool) -> Self {
+        Self {
+            own_id,
+            k,
+            relaxed,
+            buckets: vec![Bucket::new(k)],
         }
+    }
+
+    /// index of the bucket whose range covers given id
+    fn index(&self, id: &Key) -> usize {
+        self.buckets
+            .iter()
+            .position(|b| b.covers(id))
+            .expect("buckets cover the whole id space")
+    }
+
+    /// update bucket for given node, returning least-recently seen node to ping if the bucket is full.
+    /// a full bucket is split first when it may be, see above.
+    pub fn update_bucket(&mut self, node_info: NodeInfo) -> Option<NodeInfo> {
+        loop {
+            let i = self.index(node_info.get_id());
+            let bucket = &self.buckets[i];
+            if bucket.contains(&node_info) || !bucket.is_full() || !self.can_split(i, &node_info) {
+                return self.buckets[i].update(node_info);
+            }
+            let (zero, one) = self.buckets.remove(i).split();
+            // the half sharing the next bit with our own id is the closer one
+            let (far, near) = if self.own_id.bit(zero.depth) {
+                (zero, one)
+            } else {
+                (one, zero)
+            };
+            self.buckets.insert(i, near);
+            self.buckets.insert(i, far);
+        }
+    }
+
+    /// whether full bucket at given index may be split to make room for given node
+    fn can_split(&self, i: usize, node_info: &NodeInfo) -> bool {
+        let bucket = &self.buckets[i];
+        if bucket.depth >= 160 {
+            return false;
+        }
+        bucket.covers(&self.own_id)
+            || (self.relaxed
+                && self.count_closer(&self.own_id, &node_info.get_id().distance(&self.own_id))
+                    < self.k)
+    }
+
+    /// apply result of the ping returned by `update_bucket`.
+    pub fn ping_result(&mut self, head: &NodeInfo, alive: bool) {
+        let i = self.index(head.get_id());
+        self.buckets[i].ping_result(head, alive);
+    }
+
+    /// replace given node with a replacement from its bucket's cache.
+    pub fn mark_stale(&mut self, node_info: &NodeInfo) {
+        let i = self.index(node_info.get_id());
+        self.buckets[i].mark_stale(node_info);
+    }
+
+    /// record a lookup for given target in the bucket covering it.
+    pub fn touch(&mut self, target: &Key) {
+        let i = self.index(target);
+        self.buckets[i].touch();
+    }
+
+    /// random id in the range of every bucket without any lookup for longer than given duration.
+    /// the bucket covering our own id is skipped while it is empty, since there is no node to be
+    /// found in it.
+    pub fn idle_buckets(&self, idle: Duration) -> Vec<Key> {
+        self.buckets
+            .iter()
+            .filter(|b| !(b.nodes.is_empty() && b.covers(&self.own_id)))
+            .filter(|b| b.last_lookup.elapsed() > idle)
+            .map(Bucket::random_id)
+            .collect()
+    }
+
+    /// number of nodes closer to given target than given distance
+    pub fn count_closer(&self, target: &Key, distance: &Key) -> usize {
+        self.buckets
+            .iter()
+            .flat_map(|b| b.nodes.iter())
+            .filter(|c| &c.info.get_id().distance(target) < distance)
+            .count()
+    }
+
+    /// every contact of all buckets, farthest bucket first
+    pub fn contacts(&self) -> Vec<Contact> {
+        self.iter().cloned().collect()
+    }
+
+    /// iterate over every contact of all buckets, farthest bucket first
+    pub fn iter(&self) -> impl Iterator<Item = &Contact> {
+        self.buckets.iter().flat_map(|b| b.nodes.iter())
+    }
+
+    /// buckets ordered from the farthest range to the one covering our own id
+    pub fn get_buckets(&self) -> &[Bucket] {
+        &self.buckets
+    }
+
+    /// serializable copy of the whole routing table
+    pub fn snapshot(&self) -> RoutingSnapshot {
+        RoutingSnapshot {
+            own_id: self.own_id.clone(),
+            k: self.k,
+            contact_count: self.len(),
+            buckets: self.buckets.iter().map(Bucket::snapshot).collect(),
+        }
+    }
+
+    /// number of nodes in all buckets
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|b| b.nodes.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// return at most n known nodes ordered by XOR distance to given target, closest first.
+    pub fn closest(&self, target: &Key, n: usize) -> Vec<NodeInfo> {
+        let mut nodes: Vec<NodeInfo> = self
+            .buckets
+            .iter()
+            .flat_map(|b| b.nodes.iter().map(|c| c.info.clone()))
+            .collect();
+        nodes.sort_by_key(|n| n.get_id().distance(target));
+        nodes.truncate(n);
+        nodes
+    }
+}
+
+/// state of a routing table at some point, for tools to inspect what a node knows
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutingSnapshot {
+    own_id: Key,
+    k: usize,
+    contact_count: usize,
+    buckets: Vec<BucketSnapshot>,
+}
+
+impl RoutingSnapshot {
+    pub fn get_own_id(&self) -> &Key {
+        &self.own_id
+    }
+
+    pub fn get_k(&self) -> usize {
+        self.k
+    }
+
+    pub fn get_contact_count(&self) -> usize {
+        self.contact_count
+    }
+
+    /// buckets ordered from the farthest range to the one covering our own id
+    pub fn get_buckets(&self) -> &[BucketSnapshot] {
+        &self.buckets
+    }
+}
+
+/// state of a single bucket in a `RoutingSnapshot`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BucketSnapshot {
+    prefix: Key,
+    depth: u32,
+    contacts: Vec<Contact>,
+    replacements: Vec<NodeInfo>,
+    /// time elapsed since the last lookup in the range of the bucket
+    #[serde(with = "crate::config::seconds")]
+    since_lookup: Duration,
+}
+
+impl BucketSnapshot {
+    pub fn get_prefix(&self) -> &Key {
+        &self.prefix
+    }
+
+    pub fn get_depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn get_contacts(&self) -> &[Contact] {
+        &self.contacts
+    }
 
-        Self { buckets }
+    pub fn get_replacements(&self) -> &[NodeInfo] {
+        &self.replacements
     }
 
-    pub fn update_bucket(&mut self, node_info: NodeInfo, distance: Key) {
-        let i = distance.most_significant_bit();
-        println!("most significant bit {}", i);
-        self.buckets[i as usize].update(node_info);
-        // for b in self.buckets.iter() {
-        //     println!("{}", b);
-        // }
+    pub fn get_since_lookup(&self) -> Duration {
+        self.since_lookup
     }
 }
 
@@ -105,16 +529,18 @@ impl KBucket {
 mod tests {
     use super::*;
     use crate::key::Key;
-    use std::net::SocketAddrV4;
+    use std::net::SocketAddr;
+
+    const K: usize = 10;
 
     fn create_node_info(host: &str, key: &str) -> NodeInfo {
-        let host: SocketAddrV4 = host.parse().unwrap();
+        let host: SocketAddr = host.parse().unwrap();
         NodeInfo::new(host, key.into())
     }
 
     #[test]
     fn test_push_node() {
-        let mut bucket = Bucket::new();
+        let mut bucket = Bucket::new(K);
         let _ = bucket.push_back(create_node_info("127.0.0.1:1999", "key1"));
         let _ = bucket.push_back(create_node_info("127.0.0.1:2000", "key2"));
         let _ = bucket.push_back(create_node_info("127.0.0.1:2001", "key3"));
@@ -123,7 +549,7 @@ mod tests {
 
     #[test]
     fn test_remove_node() {
-        let mut bucket = Bucket::new();
+        let mut bucket = Bucket::new(K);
         let _ = bucket.push_back(create_node_info("127.0.0.1:2000", "key1"));
         let _ = bucket.push_back(create_node_info("127.0.0.1:2001", "key2"));
         let node_info = bucket.remove(1);
@@ -133,20 +559,20 @@ mod tests {
 
     #[test]
     fn test_node_move_to_tail() {
-        let mut bucket = Bucket::new();
+        let mut bucket = Bucket::new(K);
         let _ = bucket.push_back(create_node_info("127.0.0.1:1999", "key1"));
         let _ = bucket.push_back(create_node_info("127.0.0.1:2000", "key2"));
         let _ = bucket.push_back(create_node_info("127.0.0.1:2001", "key3"));
         let _ = bucket.push_back(create_node_info("127.0.0.1:2002", "key4"));
         let res = bucket.move_to_tail(0);
         assert!(res.is_ok(), "success move to tail");
-        let back = bucket.nodes.last().unwrap();
+        let back = bucket.nodes.last().unwrap().get_info();
         assert_eq!(back.get_id(), &Key::from("key1"));
     }
 
     #[test]
     fn test_node_move_to_tail_fail() {
-        let mut bucket = Bucket::new();
+        let mut bucket = Bucket::new(K);
         let _ = bucket.push_back(create_node_info("127.0.0.1:2001", "key1"));
         let _ = bucket.push_back(create_node_info("127.0.0.1:2002", "key2"));
         let res = bucket.move_to_tail(2);
@@ -156,18 +582,44 @@ mod tests {
     #[test]
     fn test_update_bucket_with_one_already_in_the_bucket() {
         let node2 = create_node_info("127.0.0.1:2002", "key2");
-        let mut bucket = Bucket::new();
+        let mut bucket = Bucket::new(K);
         let _ = bucket.push_back(create_node_info("127.0.0.1:2001", "key1"));
         let _ = bucket.push_back(node2.clone());
         let _ = bucket.push_back(create_node_info("127.0.0.1:2002", "key3"));
         let _ = bucket.push_back(create_node_info("127.0.0.1:2003", "key4"));
         bucket.update(node2.clone());
-        assert_eq!(bucket.nodes.last().unwrap(), &node2);
+        assert_eq!(bucket.nodes.last().unwrap().get_info(), &node2);
+    }
+
+    #[test]
+    fn test_update_bucket_refreshes_last_seen() {
+        let node1 = create_node_info("127.0.0.1:2001", "key1");
+        let mut bucket = Bucket::new(K);
+        bucket.update(node1.clone());
+        let first_seen = bucket.get_contacts()[0].get_last_seen();
+
+        std::thread::sleep(Duration::from_millis(10));
+        bucket.update(node1);
+        assert!(bucket.get_contacts()[0].get_last_seen() > first_seen);
+    }
+
+    #[test]
+    fn test_update_bucket_with_same_id_at_new_address() {
+        let node1 = create_node_info("127.0.0.1:2001", "key1");
+        let moved = create_node_info("127.0.0.1:3001", "key1");
+        let mut bucket = Bucket::new(K);
+        bucket.update(node1.clone());
+        let _ = bucket.push_back(create_node_info("127.0.0.1:2002", "key2"));
+
+        assert_eq!(bucket.update(moved.clone()), None);
+        assert_eq!(bucket.get_contacts().len(), 2);
+        assert_eq!(bucket.nodes.last().unwrap().get_info(), &moved);
+        assert!(bucket.contains(&node1));
     }
 
     #[test]
     fn test_update_bucket_new_node() {
-        let mut bucket = Bucket::new();
+        let mut bucket = Bucket::new(K);
         let _ = bucket.push_back(create_node_info("127.0.0.1:1999", "key1"));
         let _ = bucket.push_back(create_node_info("127.0.0.1:2000", "key2"));
         let _ = bucket.push_back(create_node_info("127.0.0.1:2001", "key3"));
@@ -175,30 +627,457 @@ mod tests {
         let node = create_node_info("127.0.0.1:2002", "new_key");
 
         bucket.update(node.clone());
-        assert_eq!(bucket.nodes.last().unwrap(), &node);
+        assert_eq!(bucket.nodes.last().unwrap().get_info(), &node);
+    }
+
+    fn create_full_bucket() -> Bucket {
+        let mut bucket = Bucket::new(K);
+        for i in 0..K {
+            let _ = bucket.push_back(create_node_info(
+                &format!("127.0.0.1:{}", 2001 + i),
+                &format!("key{}", i + 1),
+            ));
+        }
+        bucket
     }
 
-    // TODO: change when ping is implemented
     #[test]
     fn test_update_full_bucket_new_node() {
-        let mut bucket = Bucket::new();
+        let node1 = create_node_info("127.0.0.1:2001", "key1");
+        let node = create_node_info("127.0.0.1:2002", "new_key");
 
-        let node1 = create_node_info("127.0.0.1:2002", "key1");
-        let _ = bucket.push_back(node1.clone());
+        // least-recently seen node responds: keep it and discard new node
+        let mut bucket = create_full_bucket();
+        let head = bucket.update(node.clone());
+        assert_eq!(head.as_ref(), Some(&node1));
+        assert_eq!(bucket.nodes.first().unwrap().get_info(), &node1);
+        bucket.ping_result(&node1, true);
+        assert_eq!(bucket.nodes.last().unwrap().get_info(), &node1);
+        assert!(!bucket.contains(&node));
+        assert_eq!(bucket.replacements.last().unwrap(), &node);
 
-        let _ = bucket.push_back(create_node_info("127.0.0.1:2001", "key2"));
-        let _ = bucket.push_back(create_node_info("127.0.0.1:2002", "key3"));
-        let _ = bucket.push_back(create_node_info("127.0.0.1:2003", "key4"));
-        let _ = bucket.push_back(create_node_info("127.0.0.1:2004", "key5"));
-        let _ = bucket.push_back(create_node_info("127.0.0.1:2005", "key6"));
-        let _ = bucket.push_back(create_node_info("127.0.0.1:2003", "key7"));
-        let _ = bucket.push_back(create_node_info("127.0.0.1:2004", "key8"));
-        let _ = bucket.push_back(create_node_info("127.0.0.1:2005", "key9"));
-        let _ = bucket.push_back(create_node_info("127.0.0.1:2005", "key10"));
+        // least-recently seen node doesn't respond: evict it and push new node
+        let mut bucket = create_full_bucket();
+        let head = bucket.update(node.clone());
+        assert_eq!(head.as_ref(), Some(&node1));
+        bucket.ping_result(&node1, false);
+        assert_eq!(bucket.nodes.len(), K);
+        assert_eq!(bucket.nodes.last().unwrap().get_info(), &node);
+        assert!(!bucket.contains(&node1));
+        assert!(bucket.replacements.is_empty());
+    }
 
-        let node = create_node_info("127.0.0.1:2002", "new_key");
+    #[test]
+    fn test_replacement_cache_bounded() {
+        let mut bucket = create_full_bucket();
+        for i in 0..REPLACEMENT_CACHE_SIZE + 2 {
+            bucket.update(create_node_info(
+                &format!("127.0.0.1:{}", 3000 + i),
+                &format!("replacement{}", i),
+            ));
+        }
+        assert_eq!(bucket.replacements.len(), REPLACEMENT_CACHE_SIZE);
+        // oldest candidates are dropped first
+        assert_eq!(
+            bucket.get_replacements().first().unwrap(),
+            &create_node_info("127.0.0.1:3002", "replacement2")
+        );
+    }
 
-        bucket.update(node.clone());
-        assert_eq!(bucket.nodes.last().unwrap(), &node1);
+    #[test]
+    fn test_replacement_seen_again_moves_to_tail() {
+        let mut bucket = create_full_bucket();
+        let replacement1 = create_node_info("127.0.0.1:3000", "replacement1");
+        let replacement2 = create_node_info("127.0.0.1:3001", "replacement2");
+        bucket.update(replacement1.clone());
+        bucket.update(replacement2.clone());
+        bucket.update(replacement1.clone());
+        assert_eq!(bucket.get_replacements(), &[replacement2, replacement1][..]);
+    }
+
+    #[test]
+    fn test_mark_stale_replaced_from_cache() {
+        let node1 = create_node_info("127.0.0.1:2001", "key1");
+        let replacement = create_node_info("127.0.0.1:3000", "replacement");
+        let mut bucket = create_full_bucket();
+        bucket.update(replacement.clone());
+
+        bucket.mark_stale(&node1);
+        assert!(!bucket.contains(&node1));
+        assert_eq!(bucket.nodes.last().unwrap().get_info(), &replacement);
+        assert!(bucket.replacements.is_empty());
+    }
+
+    #[test]
+    fn test_mark_stale_without_replacement_keeps_node() {
+        let node1 = create_node_info("127.0.0.1:2001", "key1");
+        let mut bucket = create_full_bucket();
+
+        bucket.mark_stale(&node1);
+        bucket.mark_stale(&node1);
+        assert_eq!(bucket.nodes.len(), K);
+        assert!(bucket.contains(&node1));
+        assert_eq!(bucket.nodes[0].get_failures(), 2);
+
+        // failures are forgotten once we hear from the node again
+        bucket.update(node1.clone());
+        assert_eq!(bucket.nodes.last().unwrap().get_info(), &node1);
+        assert_eq!(bucket.nodes.last().unwrap().get_failures(), 0);
+    }
+
+    fn create_k_bucket(own_id: &Key, nodes: &[NodeInfo]) -> KBucket {
+        let mut k_bucket = KBucket::new(own_id.clone(), K, false);
+        for n in nodes {
+            k_bucket.update_bucket(n.clone());
+        }
+        k_bucket
+    }
+
+    #[test]
+    fn test_closest_empty_k_bucket() {
+        let k_bucket = KBucket::new(Key::from("own"), K, false);
+        assert!(k_bucket.closest(&Key::from("target"), K).is_empty());
+    }
+
+    #[test]
+    fn test_closest_sparse_buckets_sorted_by_distance() {
+        let own_id = Key::from("own");
+        let nodes: Vec<NodeInfo> = (0..5)
+            .map(|i| create_node_info(&format!("127.0.0.1:{}", 2000 + i), &format!("key{}", i)))
+            .collect();
+        let k_bucket = create_k_bucket(&own_id, &nodes);
+        let target = Key::from("target");
+
+        let closest = k_bucket.closest(&target, K);
+        assert_eq!(closest.len(), 5);
+        for pair in closest.windows(2) {
+            assert!(pair[0].get_id().distance(&target) <= pair[1].get_id().distance(&target));
+        }
+    }
+
+    #[test]
+    fn test_closest_truncated_to_n() {
+        let own_id = Key::from("own");
+        let nodes: Vec<NodeInfo> = (0..5)
+            .map(|i| create_node_info(&format!("127.0.0.1:{}", 2000 + i), &format!("key{}", i)))
+            .collect();
+        let k_bucket = create_k_bucket(&own_id, &nodes);
+        let target = Key::from("target");
+
+        let mut expected = nodes.clone();
+        expected.sort_by_key(|n| n.get_id().distance(&target));
+        assert_eq!(k_bucket.closest(&target, 2), expected[..2].to_vec());
+    }
+
+    #[test]
+    fn test_closest_target_in_empty_bucket() {
+        // both nodes are far from our own id, target is next to it
+        let own_id = Key::new([0; 20]);
+        let mut far = [0; 20];
+        far[0] = 0b1000_0000;
+        let mut near = [0; 20];
+        near[0] = 0b0100_0000;
+        let mut target = [0; 20];
+        target[19] = 1;
+        let far = NodeInfo::new("127.0.0.1:2000".parse().unwrap(), Key::new(far));
+        let near = NodeInfo::new("127.0.0.1:2001".parse().unwrap(), Key::new(near));
+        let k_bucket = create_k_bucket(&own_id, &[far.clone(), near.clone()]);
+
+        let closest = k_bucket.closest(&Key::new(target), K);
+        assert_eq!(closest, vec![near, far]);
+    }
+
+    #[test]
+    fn test_idle_buckets_empty_k_bucket() {
+        let k_bucket = KBucket::new(Key::from("own"), K, false);
+        assert!(k_bucket.idle_buckets(Duration::from_secs(0)).is_empty());
+    }
+
+    #[test]
+    fn test_idle_buckets() {
+        let own_id = Key::from("own");
+        let nodes: Vec<NodeInfo> = (0..40)
+            .map(|i| create_node_info(&format!("127.0.0.1:{}", 2000 + i), &format!("key{}", i)))
+            .collect();
+        let mut k_bucket = create_k_bucket(&own_id, &nodes);
+        assert!(k_bucket.buckets.len() > 1);
+
+        // nothing is idle yet
+        assert!(k_bucket.idle_buckets(Duration::from_secs(3600)).is_empty());
+
+        std::thread::sleep(Duration::from_millis(10));
+        let idle = k_bucket.idle_buckets(Duration::from_millis(5));
+        let own_bucket_empty = k_bucket.buckets.last().unwrap().nodes.is_empty();
+        assert_eq!(
+            idle.len(),
+            k_bucket.buckets.len() - own_bucket_empty as usize
+        );
+        // one id in the range of every idle bucket
+        let mut indices: Vec<usize> = idle.iter().map(|id| k_bucket.index(id)).collect();
+        indices.dedup();
+        assert_eq!(indices.len(), idle.len());
+
+        // a lookup in the range of a bucket marks it as used
+        k_bucket.touch(&idle[0]);
+        let still_idle = k_bucket.idle_buckets(Duration::from_millis(5));
+        assert_eq!(still_idle.len(), idle.len() - 1);
+        assert!(still_idle.iter().all(|id| k_bucket.index(id) != indices[0]));
+    }
+
+    #[test]
+    fn test_split_own_bucket() {
+        let own_id = Key::new([0; 20]);
+        let mut k_bucket = KBucket::new(own_id.clone(), K, false);
+        let far = Key::new([0; 20]).flip_bit(0);
+        for i in 0..K {
+            let id = if i % 2 == 0 {
+                far.random_with_prefix(1)
+            } else {
+                own_id.random_with_prefix(1)
+            };
+            k_bucket.update_bucket(NodeInfo::new(host(2000 + i), id));
+        }
+        assert_eq!(k_bucket.buckets.len(), 1);
+
+        // full bucket covering our own id is split, far half first
+        assert!(k_bucket
+            .update_bucket(NodeInfo::new(host(3000), far.random_with_prefix(1)))
+            .is_none());
+        assert_eq!(k_bucket.len(), K + 1);
+        assert!(k_bucket.buckets.len() >= 2);
+        assert!(k_bucket.buckets[0].covers(&far));
+        assert!(!k_bucket.buckets[0].covers(&own_id));
+        assert!(k_bucket.buckets.last().unwrap().covers(&own_id));
+        for bucket in k_bucket.buckets.iter() {
+            assert!(bucket.nodes.iter().all(|c| bucket.covers(c.info.get_id())));
+        }
+
+        // far half is never split without relaxed splitting
+        let mut heads = 0;
+        for i in 0..K {
+            let id = far.random_with_prefix(1);
+            if k_bucket
+                .update_bucket(NodeInfo::new(host(4000 + i), id))
+                .is_some()
+            {
+                heads += 1;
+            }
+        }
+        assert!(heads > 0);
+        assert_eq!(k_bucket.buckets[0].nodes.len(), K);
+        assert_eq!(k_bucket.buckets[0].get_depth(), 1);
+    }
+
+    #[test]
+    fn test_split_promotes_replacements() {
+        let zero = Key::new([0; 20]);
+        let one = zero.flip_bit(0);
+        let mut bucket = Bucket::new(K);
+        for i in 0..K {
+            bucket.update(NodeInfo::new(host(2000 + i), one.random_with_prefix(1)));
+        }
+        let cached: Vec<NodeInfo> = (0..3)
+            .map(|i| NodeInfo::new(host(3000 + i), zero.random_with_prefix(1)))
+            .collect();
+        for n in cached.iter() {
+            assert!(bucket.update(n.clone()).is_some());
+        }
+        assert_eq!(bucket.get_replacements().len(), 3);
+
+        let (mut near, far) = bucket.split();
+        assert_eq!(far.nodes.len(), K);
+        assert_eq!(near.nodes.len(), 3);
+        assert!(near.get_replacements().is_empty());
+
+        // cached node contacts us again, then a contact is evicted
+        assert!(near.update(cached[0].clone()).is_none());
+        let head = near.nodes[0].info.clone();
+        near.ping_result(&head, false);
+        near.mark_stale(&cached[1]);
+        let mut ids: Vec<&Key> = near.nodes.iter().map(|c| c.info.get_id()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), near.nodes.len());
+        assert_eq!(near.nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_push_back_drops_replacement() {
+        let node = create_node_info("127.0.0.1:2001", "key1");
+        let mut bucket = Bucket::new(K);
+        bucket.push_replacement(node.clone());
+        let _ = bucket.push_back(node.clone());
+        assert!(bucket.get_replacements().is_empty());
+
+        // evicting a contact leaves no duplicate behind
+        let _ = bucket.push_back(create_node_info("127.0.0.1:2002", "key2"));
+        bucket.ping_result(&node, false);
+        assert_eq!(bucket.get_contacts().len(), 1);
+        assert!(!bucket.contains(&node));
+    }
+
+    /// ids in the far half of the id space of node 0, farthest first
+    fn unbalanced_ids(n: usize) -> Vec<Key> {
+        let own_id = Key::new([0; 20]);
+        let mut ids: Vec<Key> = (0..n)
+            .map(|_| own_id.flip_bit(0).random_with_prefix(1))
+            .collect();
+        ids.sort_by_key(|id| std::cmp::Reverse(id.distance(&own_id)));
+        ids
+    }
+
+    #[test]
+    fn test_relaxed_splitting_keeps_k_closest() {
+        let own_id = Key::new([0; 20]);
+        let ids = unbalanced_ids(3 * K);
+        let mut expected = ids.clone();
+        expected.reverse();
+        expected.truncate(K);
+
+        let mut strict = KBucket::new(own_id.clone(), K, false);
+        let mut relaxed = KBucket::new(own_id.clone(), K, true);
+        for (i, id) in ids.iter().enumerate() {
+            strict.update_bucket(NodeInfo::new(host(2000 + i), id.clone()));
+            relaxed.update_bucket(NodeInfo::new(host(2000 + i), id.clone()));
+        }
+        let closest_ids = |k_bucket: &KBucket| -> Vec<Key> {
+            k_bucket
+                .closest(&own_id, K)
+                .iter()
+                .map(|n| n.get_id().clone())
+                .collect()
+        };
+        // all nodes are in the far half, which only keeps the first nodes seen
+        assert_eq!(strict.len(), K);
+        assert_ne!(closest_ids(&strict), expected);
+        assert_eq!(closest_ids(&relaxed), expected);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let own_id = Key::from("own");
+        let nodes: Vec<NodeInfo> = (0..40)
+            .map(|i| create_node_info(&format!("127.0.0.1:{}", 2000 + i), &format!("key{}", i)))
+            .collect();
+        let mut k_bucket = create_k_bucket(&own_id, &nodes);
+        k_bucket.mark_stale(&nodes[0]);
+
+        let snapshot = k_bucket.snapshot();
+        assert_eq!(snapshot.get_own_id(), &own_id);
+        assert_eq!(snapshot.get_k(), K);
+        assert_eq!(snapshot.get_contact_count(), k_bucket.len());
+        assert_eq!(snapshot.get_buckets().len(), k_bucket.get_buckets().len());
+        let contacts: Vec<Contact> = snapshot
+            .get_buckets()
+            .iter()
+            .flat_map(|b| b.get_contacts().iter().cloned())
+            .collect();
+        assert_eq!(contacts, k_bucket.iter().cloned().collect::<Vec<_>>());
+        let failures: u32 = contacts.iter().map(Contact::get_failures).sum();
+        let kept = k_bucket.iter().any(|c| c.get_info() == &nodes[0]);
+        assert_eq!(failures, kept as u32);
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let restored: RoutingSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.get_buckets().len(), snapshot.get_buckets().len());
+        assert_eq!(restored.get_contact_count(), snapshot.get_contact_count());
+    }
+
+    fn host(port: usize) -> SocketAddr {
+        format!("127.0.0.1:{}", port).parse().unwrap()
+    }
+
+    /// routing table of 160 fixed buckets, bucket i holding nodes whose distance has i leading
+    /// zeros, to compare the routing tree against
+    struct FlatKBucket {
+        own_id: Key,
+        buckets: Vec<Bucket>,
+    }
+
+    impl FlatKBucket {
+        fn new(own_id: Key) -> Self {
+            Self {
+                own_id,
+                buckets: (0..160).map(|_| Bucket::new(K)).collect(),
+            }
+        }
+
+        fn update_bucket(&mut self, node_info: NodeInfo) -> Option<NodeInfo> {
+            let i = node_info
+                .get_id()
+                .distance(&self.own_id)
+                .most_significant_bit();
+            self.buckets[i as usize].update(node_info)
+        }
+
+        fn closest(&self, target: &Key, n: usize) -> Vec<NodeInfo> {
+            let mut nodes: Vec<NodeInfo> = self
+                .buckets
+                .iter()
+                .flat_map(|b| b.nodes.iter().map(|c| c.info.clone()))
+                .collect();
+            nodes.sort_by_key(|n| n.get_id().distance(target));
+            nodes.truncate(n);
+            nodes
+        }
+    }
+
+    fn random_ids(own_id: &Key, n: usize) -> Vec<Key> {
+        // half of the nodes close to our own id, so that both layouts have many buckets
+        (0..n)
+            .map(|i| {
+                if i % 2 == 0 {
+                    Key::random()
+                } else {
+                    own_id.random_with_prefix((i % 24) as u32)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lookup_same_as_flat_layout() {
+        let own_id = Key::random();
+        let mut flat = FlatKBucket::new(own_id.clone());
+        let mut tree = KBucket::new(own_id.clone(), K, false);
+        for (i, id) in random_ids(&own_id, 500).into_iter().enumerate() {
+            let node_info = NodeInfo::new(host(2000 + i), id);
+            assert_eq!(
+                tree.update_bucket(node_info.clone()),
+                flat.update_bucket(node_info)
+            );
+        }
+        assert!(tree.buckets.len() > 1);
+
+        let mut targets = random_ids(&own_id, 50);
+        targets.push(own_id);
+        for target in targets.iter() {
+            assert_eq!(tree.closest(target, K), flat.closest(target, K));
+        }
+    }
+
+    #[test]
+    fn test_relaxed_lookup_at_least_as_close_as_flat_layout() {
+        let own_id = Key::new([0; 20]);
+        let mut flat = FlatKBucket::new(own_id.clone());
+        let mut tree = KBucket::new(own_id.clone(), K, true);
+        let mut ids = random_ids(&own_id, 300);
+        ids.extend(unbalanced_ids(100));
+        for (i, id) in ids.into_iter().enumerate() {
+            let node_info = NodeInfo::new(host(2000 + i), id);
+            flat.update_bucket(node_info.clone());
+            tree.update_bucket(node_info);
+        }
+
+        let mut targets = random_ids(&own_id, 50);
+        targets.push(own_id);
+        for target in targets.iter() {
+            let tree_closest = tree.closest(target, K);
+            let flat_closest = flat.closest(target, K);
+            assert_eq!(tree_closest.len(), flat_closest.len());
+            for (t, f) in tree_closest.iter().zip(flat_closest.iter()) {
+                assert!(t.get_id().distance(target) <= f.get_id().distance(target));
+            }
+        }
     }
 }
@@ -0,0 +1,285 @@
+use {
+    crate::{
+        error::{Error, Result},
+        frame::{read_message, write_message},
+        request::{Request, RequestId},
+        response::Response,
+    },
+    async_std::{
+        net::{TcpStream, ToSocketAddrs},
+        sync::Mutex,
+        task,
+    },
+    futures::channel::oneshot,
+    std::{collections::HashMap, net::Shutdown, sync::Arc},
+};
+
+type InFlightTable = Arc<std::sync::Mutex<HashMap<RequestId, oneshot::Sender<Response>>>>;
+
+/// client side of a connection to a single node.
+/// several requests can be sent concurrently over the same connection; each response is routed
+/// to the request waiting for it using the request id.
+pub struct Connection {
+    stream: TcpStream,
+    write_lock: Mutex<()>,
+    in_flight: InFlightTable,
+    max_frame_size: usize,
+}
+
+impl Connection {
+    /// connect to given address and start reading responses in the background.
+    pub async fn connect<A: ToSocketAddrs>(addr: A, max_frame_size: usize) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        let in_flight: InFlightTable = Default::default();
+
+        let reader = stream.clone();
+        let table = in_flight.clone();
+        task::spawn(async move { read_loop(reader, table, max_frame_size).await });
+
+        Ok(Self {
+            stream,
+            write_lock: Mutex::new(()),
+            in_flight,
+            max_frame_size,
+        })
+    }
+
+    /// send given request and wait for the response carrying the same request id.
+    /// fails if the response answers a different rpc than the request, or isn't signed by the
+    /// node it claims to be from.
+    pub async fn send(&self, req: &Request) -> Result<Response> {
+        let (sender, receiver) = oneshot::channel();
+        let pending = Pending::register(&self.in_flight, req.get_id(), sender);
+
+        {
+            let _guard = self.write_lock.lock().await;
+            write_message(&mut &self.stream, req, self.max_frame_size).await?;
+        }
+
+        let res = receiver.await.map_err(|_| Error::ConnectionClosed)?;
+        drop(pending);
+        if res.get_request_rpc() != Some(req.get_rpc()) {
+            return Err(Error::UnexpectedResponse(format!(
+                "response to request {} answers {:?}, expected {:?}",
+                req.get_id(),
+                res.get_request_rpc(),
+                req.get_rpc()
+            )));
+        }
+        res.verify()?;
+        Ok(res)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // stop the reader task
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// entry of the in-flight table, removed when the waiting request finishes or is dropped.
+struct Pending<'a> {
+    id: RequestId,
+    in_flight: &'a InFlightTable,
+}
+
+impl<'a> Pending<'a> {
+    fn register(
+        in_flight: &'a InFlightTable,
+        id: RequestId,
+        sender: oneshot::Sender<Response>,
+    ) -> Self {
+        in_flight.lock().unwrap().insert(id, sender);
+        Self { id, in_flight }
+    }
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// read responses until the connection is closed and route each to the request waiting for it.
+/// responses without a matching request in flight are discarded.
+async fn read_loop(mut stream: TcpStream, in_flight: InFlightTable, max_frame_size: usize) {
+    loop {
+        let res: Response = match read_message(&mut stream, max_frame_size).await {
+            Ok(Some(res)) => res,
+            Ok(None) => break,
+            Err(e) => {
+                println!("Response read fail: {}", e);
+                break;
+            }
+        };
+        let sender = res
+            .get_request_id()
+            .and_then(|id| in_flight.lock().unwrap().remove(&id));
+        match sender {
+            Some(sender) => {
+                let _ = sender.send(res);
+            }
+            None => println!("Unsolicited response discarded: {:?}", res),
+        }
+    }
+    // dropping senders wakes up every request still waiting with `ConnectionClosed`
+    in_flight.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {
+        crate::{
+            frame::DEFAULT_MAX_FRAME_SIZE,
+            identity::Identity,
+            key::Key,
+            node::{Node, NodeInfo},
+            response::ResponseBody,
+            rpc::Rpc,
+            server,
+        },
+        async_std::{net::TcpListener, sync::RwLock, task::block_on},
+        std::net::SocketAddr,
+    };
+
+    async fn start_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = listener.local_addr().unwrap();
+        let node = Arc::new(RwLock::new(Node::new(host).unwrap()));
+        task::spawn(server::serve(listener, node, DEFAULT_MAX_FRAME_SIZE));
+        host
+    }
+
+    #[test]
+    fn test_pipelined_requests_on_single_connection() {
+        block_on(async {
+            let to = start_server().await;
+            let conn = Connection::connect(to, DEFAULT_MAX_FRAME_SIZE)
+                .await
+                .unwrap();
+
+            let ping = Request::new(None, Rpc::Ping, to);
+            let store = Request::new(None, Rpc::Store("k1".into(), b"v1".to_vec(), None), to);
+            let find = Request::new(None, Rpc::FindNode("k1".into()), to);
+            let (ping_res, store_res, find_res) =
+                futures::join!(conn.send(&ping), conn.send(&store), conn.send(&find));
+
+            let ping_res = ping_res.unwrap();
+            assert_eq!(ping_res.get_request_id(), Some(ping.get_id()));
+            assert!(matches!(ping_res.get_body(), Some(ResponseBody::PONG)));
+            let store_res = store_res.unwrap();
+            assert_eq!(store_res.get_request_id(), Some(store.get_id()));
+            assert!(matches!(store_res.get_body(), Some(ResponseBody::STORED)));
+            let find_res = find_res.unwrap();
+            assert_eq!(find_res.get_request_id(), Some(find.get_id()));
+            assert!(matches!(find_res.get_body(), Some(ResponseBody::NODES(_))));
+            assert!(conn.in_flight.lock().unwrap().is_empty());
+        });
+    }
+
+    /// start a server which reads a single request, writes given responses to it and closes
+    /// the connection
+    async fn start_fake_server<F>(responses: F) -> SocketAddr
+    where
+        F: Fn(&Request) -> Vec<serde_json::Value> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = listener.local_addr().unwrap();
+        task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let req: Request = read_message(&mut stream, DEFAULT_MAX_FRAME_SIZE)
+                .await
+                .unwrap()
+                .unwrap();
+            for res in responses(&req) {
+                write_message(&mut stream, &res, DEFAULT_MAX_FRAME_SIZE)
+                    .await
+                    .unwrap();
+            }
+        });
+        host
+    }
+
+    fn pong(req: &Request) -> serde_json::Value {
+        let identity = Identity::from_seed(&[1; 32]).unwrap();
+        let from = NodeInfo::new(*req.get_to(), identity.get_id().clone());
+        let mut res = Response::from_request(req, from);
+        res.set_body(Some(ResponseBody::PONG));
+        res.sign(&identity);
+        serde_json::to_value(res).unwrap()
+    }
+
+    #[test]
+    fn test_unsolicited_response_discarded() {
+        block_on(async {
+            let to = start_fake_server(|req| {
+                let other = Request::new(None, Rpc::Ping, *req.get_to());
+                vec![pong(&other), pong(req)]
+            })
+            .await;
+            let conn = Connection::connect(to, DEFAULT_MAX_FRAME_SIZE)
+                .await
+                .unwrap();
+
+            let req = Request::new(None, Rpc::Ping, to);
+            let res = conn.send(&req).await.unwrap();
+            assert_eq!(res.get_request_id(), Some(req.get_id()));
+        });
+    }
+
+    #[test]
+    fn test_mismatched_response_rejected() {
+        block_on(async {
+            let to = start_fake_server(|req| {
+                // echo the id of the request, but answer another rpc
+                let mut res = pong(req);
+                res["request_rpc"] = serde_json::to_value(Rpc::FindNode("k1".into())).unwrap();
+                vec![res]
+            })
+            .await;
+            let conn = Connection::connect(to, DEFAULT_MAX_FRAME_SIZE)
+                .await
+                .unwrap();
+
+            let req = Request::new(None, Rpc::Ping, to);
+            let res = conn.send(&req).await;
+            assert!(matches!(res, Err(Error::UnexpectedResponse(_))));
+        });
+    }
+
+    #[test]
+    fn test_forged_response_rejected() {
+        block_on(async {
+            let to = start_fake_server(|req| {
+                // signed by the fake server, but claiming to come from another node
+                let mut res = pong(req);
+                res["from"]["id"] = serde_json::to_value(Key::random()).unwrap();
+                vec![res]
+            })
+            .await;
+            let conn = Connection::connect(to, DEFAULT_MAX_FRAME_SIZE)
+                .await
+                .unwrap();
+
+            let req = Request::new(None, Rpc::Ping, to);
+            let res = conn.send(&req).await;
+            assert!(matches!(res, Err(Error::InvalidSignature(_))));
+        });
+    }
+
+    #[test]
+    fn test_connection_closed_before_response() {
+        block_on(async {
+            let to = start_fake_server(|_| vec![]).await;
+            let conn = Connection::connect(to, DEFAULT_MAX_FRAME_SIZE)
+                .await
+                .unwrap();
+
+            let req = Request::new(None, Rpc::Ping, to);
+            let res = conn.send(&req).await;
+            assert!(matches!(res, Err(Error::ConnectionClosed)));
+        });
+    }
+}
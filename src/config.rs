@@ -0,0 +1,327 @@
+use {
+    crate::{
+        bucket::DEFAULT_K,
+        contacts::SNAPSHOT_INTERVAL,
+        error::{Error, Result},
+        expiration::EXPIRE_INTERVAL,
+        frame::DEFAULT_MAX_FRAME_SIZE,
+        lookup::DEFAULT_ALPHA,
+        refresh::DEFAULT_REFRESH_INTERVAL,
+        replication::{DEFAULT_MIN_REPLICAS, REPUBLISH_INTERVAL},
+        request::RPC_TIMEOUT,
+        udp::{MAX_UDP_RETRIES, UDP_RETRIES},
+    },
+    serde::{Deserialize, Serialize},
+    std::{fs, path::Path, time::Duration},
+};
+
+/// parameters of a node. every field missing from a config file takes its default value.
+/// durations are written in seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// bucket size, also the number of nodes returned by FIND_NODE and replicas of a value
+    k: usize,
+    /// split full buckets which don't cover our own id to keep the k closest nodes to it
+    relaxed_splitting: bool,
+    /// number of requests sent concurrently in a single lookup round
+    alpha: usize,
+    /// time to wait for a response before treating the remote node as unresponsive
+    #[serde(with = "seconds")]
+    rpc_timeout: Duration,
+    /// number of times a UDP request is resent when no response arrives
+    udp_retries: u32,
+    /// maximum size of a single request or response in bytes
+    max_frame_size: usize,
+    /// number of nodes which must store a value for a put to succeed
+    min_replicas: usize,
+    #[serde(with = "seconds")]
+    expire_interval: Duration,
+    /// time a bucket can stay without lookup before it is refreshed
+    #[serde(with = "seconds")]
+    refresh_interval: Duration,
+    #[serde(with = "seconds")]
+    republish_interval: Duration,
+    #[serde(with = "seconds")]
+    snapshot_interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            k: DEFAULT_K,
+            relaxed_splitting: true,
+            alpha: DEFAULT_ALPHA,
+            rpc_timeout: RPC_TIMEOUT,
+            udp_retries: UDP_RETRIES,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            min_replicas: DEFAULT_MIN_REPLICAS,
+            expire_interval: EXPIRE_INTERVAL,
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            republish_interval: REPUBLISH_INTERVAL,
+            snapshot_interval: SNAPSHOT_INTERVAL,
+        }
+    }
+}
+
+impl Config {
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder {
+            config: Self::default(),
+        }
+    }
+
+    /// load config from given file, read as TOML if its extension is `.toml` and as JSON
+    /// otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let config: Self = match path.extension() {
+            Some(ext) if ext == "toml" => toml::from_slice(&fs::read(path)?)?,
+            _ => serde_json::from_slice(&fs::read(path)?)?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// builder starting from the values of this config, e.g. to override some of a loaded file
+    pub fn to_builder(&self) -> ConfigBuilder {
+        ConfigBuilder {
+            config: self.clone(),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.k == 0 {
+            return Err(Error::InvalidConfig("k must be at least 1".to_owned()));
+        }
+        if self.alpha == 0 || self.alpha > self.k {
+            return Err(Error::InvalidConfig(format!(
+                "alpha must be between 1 and k ({}), given {}",
+                self.k, self.alpha
+            )));
+        }
+        if self.min_replicas > self.k {
+            return Err(Error::InvalidConfig(format!(
+                "min_replicas must be at most k ({}), given {}",
+                self.k, self.min_replicas
+            )));
+        }
+        if self.udp_retries > MAX_UDP_RETRIES {
+            return Err(Error::InvalidConfig(format!(
+                "udp_retries must be at most {}, given {}",
+                MAX_UDP_RETRIES, self.udp_retries
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn get_k(&self) -> usize {
+        self.k
+    }
+
+    pub fn get_relaxed_splitting(&self) -> bool {
+        self.relaxed_splitting
+    }
+
+    pub fn get_alpha(&self) -> usize {
+        self.alpha
+    }
+
+    pub fn get_rpc_timeout(&self) -> Duration {
+        self.rpc_timeout
+    }
+
+    pub fn get_udp_retries(&self) -> u32 {
+        self.udp_retries
+    }
+
+    pub fn get_max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    pub fn get_min_replicas(&self) -> usize {
+        self.min_replicas
+    }
+
+    pub fn get_expire_interval(&self) -> Duration {
+        self.expire_interval
+    }
+
+    pub fn get_refresh_interval(&self) -> Duration {
+        self.refresh_interval
+    }
+
+    pub fn get_republish_interval(&self) -> Duration {
+        self.republish_interval
+    }
+
+    pub fn get_snapshot_interval(&self) -> Duration {
+        self.snapshot_interval
+    }
+}
+
+/// builds a `Config`, checking the values are consistent with each other
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn k(mut self, k: usize) -> Self {
+        self.config.k = k;
+        self
+    }
+
+    pub fn relaxed_splitting(mut self, relaxed: bool) -> Self {
+        self.config.relaxed_splitting = relaxed;
+        self
+    }
+
+    pub fn alpha(mut self, alpha: usize) -> Self {
+        self.config.alpha = alpha;
+        self
+    }
+
+    pub fn rpc_timeout(mut self, timeout: Duration) -> Self {
+        self.config.rpc_timeout = timeout;
+        self
+    }
+
+    pub fn udp_retries(mut self, retries: u32) -> Self {
+        self.config.udp_retries = retries;
+        self
+    }
+
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.config.max_frame_size = size;
+        self
+    }
+
+    pub fn min_replicas(mut self, min_replicas: usize) -> Self {
+        self.config.min_replicas = min_replicas;
+        self
+    }
+
+    pub fn expire_interval(mut self, interval: Duration) -> Self {
+        self.config.expire_interval = interval;
+        self
+    }
+
+    pub fn refresh_interval(mut self, interval: Duration) -> Self {
+        self.config.refresh_interval = interval;
+        self
+    }
+
+    pub fn republish_interval(mut self, interval: Duration) -> Self {
+        self.config.republish_interval = interval;
+        self
+    }
+
+    pub fn snapshot_interval(mut self, interval: Duration) -> Self {
+        self.config.snapshot_interval = interval;
+        self
+    }
+
+    pub fn build(self) -> Result<Config> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// (de)serialize a duration as a number of seconds
+pub(crate) mod seconds {
+    use {
+        serde::{Deserialize, Deserializer, Serializer},
+        std::time::Duration,
+    };
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        // larger values don't fit in a Duration
+        if !secs.is_finite() || secs < 0.0 || secs >= u64::MAX as f64 {
+            return Err(serde::de::Error::custom(format!(
+                "invalid number of seconds: {}",
+                secs
+            )));
+        }
+        Ok(Duration::from_secs_f64(secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::env};
+
+    #[test]
+    fn test_builder() {
+        let config = Config::builder().k(4).alpha(2).build().unwrap();
+        assert_eq!(config.get_k(), 4);
+        assert_eq!(config.get_alpha(), 2);
+        assert_eq!(config.get_rpc_timeout(), RPC_TIMEOUT);
+
+        assert!(matches!(
+            Config::builder().k(0).build(),
+            Err(Error::InvalidConfig(_))
+        ));
+        // default alpha is larger than k
+        assert!(matches!(
+            Config::builder().k(2).build(),
+            Err(Error::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            Config::builder().udp_retries(u32::MAX).build(),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_load() {
+        let path = env::temp_dir().join(format!("kadrs_config_{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{ "k": 20, "rpc_timeout": 0.5, "refresh_interval": 600 }"#,
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.get_k(), 20);
+        assert_eq!(config.get_rpc_timeout(), Duration::from_millis(500));
+        assert_eq!(config.get_refresh_interval(), Duration::from_secs(600));
+        assert_eq!(config.get_alpha(), DEFAULT_ALPHA);
+
+        // typos are reported instead of silently using the default
+        fs::write(&path, r#"{ "kk": 20 }"#).unwrap();
+        assert!(Config::load(&path).is_err());
+        // durations which don't fit are reported instead of panicking
+        fs::write(&path, r#"{ "rpc_timeout": 1e20 }"#).unwrap();
+        assert!(Config::load(&path).is_err());
+        fs::write(&path, r#"{ "rpc_timeout": -1 }"#).unwrap();
+        assert!(Config::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_toml() {
+        let path = env::temp_dir().join(format!("kadrs_config_{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "k = 20\nrpc_timeout = 0.5\nrefresh_interval = 600\nrelaxed_splitting = false\n",
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.get_k(), 20);
+        assert_eq!(config.get_rpc_timeout(), Duration::from_millis(500));
+        assert_eq!(config.get_refresh_interval(), Duration::from_secs(600));
+        assert_eq!(config.get_alpha(), DEFAULT_ALPHA);
+        assert!(!config.get_relaxed_splitting());
+
+        fs::write(&path, "kk = 20\n").unwrap();
+        assert!(Config::load(&path).is_err());
+        // JSON is not accepted in a .toml file
+        fs::write(&path, r#"{ "k": 20 }"#).unwrap();
+        assert!(Config::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+use {
+    crate::{bucket::Contact, error::Result, node::Node},
+    async_std::{sync::RwLock, task},
+    std::{
+        cmp::Reverse,
+        fs, io,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::Duration,
+    },
+};
+
+/// how often contacts of the routing table are saved
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// name of the file contacts are saved to in the data directory
+pub const CONTACTS_FILE: &str = "contacts.json";
+
+/// save given contacts to given file, replacing the previous snapshot at once
+pub fn save_contacts<P: AsRef<Path>>(path: P, contacts: &[Contact]) -> Result<()> {
+    let path = path.as_ref();
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec(contacts)?)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// load contacts saved by `save_contacts`, most recently seen first.
+/// a missing file means there is no contact to restore.
+pub fn load_contacts<P: AsRef<Path>>(path: P) -> Result<Vec<Contact>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut contacts: Vec<Contact> = serde_json::from_slice(&bytes)?;
+    contacts.sort_by_key(|c| Reverse(c.get_last_seen()));
+    Ok(contacts)
+}
+
+/// save contacts of the routing table to given file every given interval
+pub async fn snapshot_loop(node: Arc<RwLock<Node>>, path: PathBuf, interval: Duration) {
+    loop {
+        task::sleep(interval).await;
+        let contacts = node.read().await.contacts();
+        if let Err(e) = save_contacts(&path, &contacts) {
+            println!("Contacts snapshot fail: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{key::Key, node::NodeInfo},
+        std::{env, net::SocketAddr},
+    };
+
+    #[test]
+    fn test_save_and_load_contacts() {
+        let path = env::temp_dir().join(format!("kadrs_contacts_{}", std::process::id()));
+        let host = |port| -> NodeInfo {
+            let host: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+            NodeInfo::new(host, Key::random())
+        };
+        let older = Contact::new(host(2000));
+        std::thread::sleep(Duration::from_millis(10));
+        let newer = Contact::new(host(2001));
+
+        save_contacts(&path, &[older.clone(), newer.clone()]).unwrap();
+        let contacts = load_contacts(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(contacts, vec![newer, older]);
+    }
+
+    #[test]
+    fn test_load_missing_contacts() {
+        let path = env::temp_dir().join(format!("kadrs_no_contacts_{}", std::process::id()));
+        assert!(load_contacts(&path).unwrap().is_empty());
+    }
+}
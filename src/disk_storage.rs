@@ -0,0 +1,244 @@
+use {
+    crate::{
+        error::Result,
+        in_memory_hash_table::{Entry, Table},
+        key::Key,
+        storage::Storage,
+    },
+    std::{
+        fs::{self, File},
+        io::{self, Write as _},
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    },
+};
+
+/// storage keeping every value in a file of given directory, named after the hex of its key.
+/// values are also kept in memory, and reloaded from the directory when opened again.
+///
+/// a value is only kept once its file is written, so that a failed write is reported to the
+/// caller instead of being lost on restart.
+pub struct DiskStorage {
+    dir: PathBuf,
+    table: Table,
+}
+
+impl DiskStorage {
+    /// open storage at given directory, creating it if it doesn't exist.
+    /// files which can't be read as a value are skipped, expired values and files left by an
+    /// interrupted write are removed. fails if the directory can't be written.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let probe = dir.join("probe");
+        write_file(&probe, &[])?;
+        fs::remove_file(&probe)?;
+
+        let mut table = Table::new();
+        let now = SystemTime::now();
+        for file in fs::read_dir(&dir)? {
+            let path = file?.path();
+            if path.extension() == Some("tmp".as_ref()) {
+                fs::remove_file(&path)?;
+                continue;
+            }
+            let key = match path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(Key::from_hex)
+            {
+                Some(key) => key,
+                None => continue,
+            };
+            let entry = match read_entry(&path) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    println!("Skipping unreadable value {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if entry.is_expired(now) {
+                fs::remove_file(&path)?;
+                continue;
+            }
+            table.insert(key, entry);
+        }
+
+        Ok(Self { dir, table })
+    }
+
+    fn path(&self, key: &Key) -> PathBuf {
+        self.dir.join(key.to_hex())
+    }
+}
+
+fn read_entry(path: &Path) -> Result<Entry> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+fn remove_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// write data to a temporary file synced to disk, then rename it over given path and sync the
+/// directory, so that a crash leaves either the previous value or the new one behind
+fn write_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    match path.parent() {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+impl Storage for DiskStorage {
+    fn get(&self, key: &Key) -> Option<&Vec<u8>> {
+        self.table.get(key)
+    }
+
+    fn put(&mut self, key: Key, value: Vec<u8>, ttl: Duration) -> Result<Option<Vec<u8>>> {
+        let entry = Entry::new(value, ttl);
+        write_file(&self.path(&key), &serde_json::to_vec(&entry)?)?;
+
+        let now = SystemTime::now();
+        Ok(self
+            .table
+            .insert(key, entry)
+            .filter(|e| !e.is_expired(now))
+            .map(|e| e.get_value().clone()))
+    }
+
+    fn stored_before(&self, age: Duration) -> Vec<(Key, Vec<u8>, Duration)> {
+        self.table.stored_before(age)
+    }
+
+    fn entries(&self) -> Vec<(Key, usize, Duration)> {
+        self.table.entries()
+    }
+
+    fn remove_expired(&mut self) -> Result<usize> {
+        let expired = self.table.drain_expired();
+        for key in expired.iter() {
+            remove_file(&self.path(key))?;
+        }
+        Ok(expired.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::in_memory_hash_table::DEFAULT_TTL, std::env};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("kadrs_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_values_reloaded_on_open() {
+        let dir = temp_dir("reload");
+        {
+            let mut storage = DiskStorage::open(&dir).unwrap();
+            storage
+                .put("k1".into(), b"v1".to_vec(), DEFAULT_TTL)
+                .unwrap();
+            storage
+                .put("k2".into(), b"v2".to_vec(), DEFAULT_TTL)
+                .unwrap();
+            let prev = storage
+                .put("k2".into(), b"v3".to_vec(), DEFAULT_TTL)
+                .unwrap();
+            assert_eq!(prev, Some(b"v2".to_vec()));
+        }
+
+        let storage = DiskStorage::open(&dir).unwrap();
+        assert_eq!(storage.get(&"k1".into()), Some(&b"v1".to_vec()));
+        assert_eq!(storage.get(&"k2".into()), Some(&b"v3".to_vec()));
+        assert_eq!(storage.get(&"k3".into()), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_expired_values_removed() {
+        let dir = temp_dir("expired");
+        let mut storage = DiskStorage::open(&dir).unwrap();
+        storage
+            .put("k1".into(), b"v1".to_vec(), Duration::from_secs(0))
+            .unwrap();
+        storage
+            .put("k2".into(), b"v2".to_vec(), Duration::from_secs(0))
+            .unwrap();
+        storage
+            .put("k3".into(), b"v3".to_vec(), DEFAULT_TTL)
+            .unwrap();
+        assert_eq!(storage.get(&"k1".into()), None);
+        assert_eq!(storage.remove_expired().unwrap(), 2);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // expired on disk are removed when opened
+        storage
+            .put("k1".into(), b"v1".to_vec(), Duration::from_secs(0))
+            .unwrap();
+        drop(storage);
+        let storage = DiskStorage::open(&dir).unwrap();
+        assert_eq!(storage.get(&"k3".into()), Some(&b"v3".to_vec()));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_failure_reported() {
+        let dir = temp_dir("write_failure");
+        let mut storage = DiskStorage::open(&dir).unwrap();
+        storage
+            .put("k1".into(), b"v1".to_vec(), DEFAULT_TTL)
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(storage
+            .put("k1".into(), b"v2".to_vec(), DEFAULT_TTL)
+            .is_err());
+        assert!(storage
+            .put("k2".into(), b"v2".to_vec(), DEFAULT_TTL)
+            .is_err());
+        assert_eq!(storage.get(&"k1".into()), Some(&b"v1".to_vec()));
+        assert_eq!(storage.get(&"k2".into()), None);
+
+        // directory which can't be created or written is rejected when opened
+        fs::write(&dir, "not a directory").unwrap();
+        assert!(DiskStorage::open(dir.join("values")).is_err());
+        fs::remove_file(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unreadable_files_skipped() {
+        let dir = temp_dir("unreadable");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("README"), "not a value").unwrap();
+        fs::write(dir.join(Key::from("k1").to_hex()), "not json").unwrap();
+        let storage = DiskStorage::open(&dir).unwrap();
+        assert_eq!(storage.get(&"k1".into()), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_interrupted_writes_removed() {
+        let dir = temp_dir("interrupted");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(Key::from("k1").to_hex());
+        fs::write(path.with_extension("tmp"), "partial").unwrap();
+        let storage = DiskStorage::open(&dir).unwrap();
+        assert_eq!(storage.get(&"k1".into()), None);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        drop(storage);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
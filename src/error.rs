@@ -10,14 +10,25 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     InvalidRequest(String),
     RequestParse(String),
+    UnexpectedResponse(String),
+    FrameTooLarge(usize, usize),
+    IncompleteFrame(usize, usize),
+    ConnectionClosed,
+    NoSeedReachable,
+    InvalidIdentity(String),
+    InvalidSignature(String),
+    InvalidConfig(String),
+    AdminRefused(String),
 
     IndexOutOfBounds(usize, usize),
     FromUtf8(std::string::FromUtf8Error),
 
     SerdeJson(serde_json::error::Error),
+    Toml(toml::de::Error),
     Io(std::io::Error),
     AddrParse(std::net::AddrParseError),
     CapacityError(arrayvec::CapacityError<NodeInfo>),
+    Timeout(async_std::future::TimeoutError),
     NoneError,
 }
 
@@ -29,7 +40,9 @@ impl error::Error for Error {
             Io(e) => Some(e),
             AddrParse(e) => Some(e),
             CapacityError(e) => Some(e),
+            Timeout(e) => Some(e),
             SerdeJson(e) => Some(e),
+            Toml(e) => Some(e),
             FromUtf8(e) => Some(e),
             _ => None,
         }
@@ -43,6 +56,23 @@ impl fmt::Display for Error {
         match self {
             InvalidRequest(msg) => write!(f, "Invalid request: {}", msg),
             RequestParse(invalid_str) => write!(f, "Cannot parse request string: {}", invalid_str),
+            UnexpectedResponse(msg) => write!(f, "Unexpected response: {}", msg),
+            FrameTooLarge(size, max) => write!(
+                f,
+                "Frame too large, given {} bytes, expected at most {}",
+                size, max
+            ),
+            ConnectionClosed => write!(f, "Connection closed before response arrived"),
+            NoSeedReachable => write!(f, "None of the bootstrap seeds responded"),
+            InvalidIdentity(msg) => write!(f, "Invalid identity: {}", msg),
+            InvalidSignature(msg) => write!(f, "Invalid signature: {}", msg),
+            InvalidConfig(msg) => write!(f, "Invalid config: {}", msg),
+            AdminRefused(msg) => write!(f, "Admin request refused: {}", msg),
+            IncompleteFrame(received, expected) => write!(
+                f,
+                "Incomplete frame, received {} bytes, expected {}",
+                received, expected
+            ),
             IndexOutOfBounds(received, bounds) => write!(
                 f,
                 "Index out of bounds, given {}, expected smaller than {}",
@@ -52,7 +82,9 @@ impl fmt::Display for Error {
             Io(e) => e.fmt(f),
             AddrParse(e) => e.fmt(f),
             CapacityError(e) => e.fmt(f),
+            Timeout(e) => e.fmt(f),
             SerdeJson(e) => e.fmt(f),
+            Toml(e) => e.fmt(f),
             NoneError => write!(f, "NoneError"),
         }
     }
@@ -76,6 +108,12 @@ impl From<arrayvec::CapacityError<NodeInfo>> for Error {
     }
 }
 
+impl From<async_std::future::TimeoutError> for Error {
+    fn from(error: async_std::future::TimeoutError) -> Self {
+        Error::Timeout(error)
+    }
+}
+
 impl From<std::option::NoneError> for Error {
     fn from(_: std::option::NoneError) -> Self {
         Error::NoneError
@@ -88,6 +126,12 @@ impl From<serde_json::error::Error> for Error {
     }
 }
 
+impl From<toml::de::Error> for Error {
+    fn from(error: toml::de::Error) -> Self {
+        Error::Toml(error)
+    }
+}
+
 impl From<std::string::FromUtf8Error> for Error {
     fn from(error: std::string::FromUtf8Error) -> Self {
         Error::FromUtf8(error)
@@ -0,0 +1,21 @@
+use {
+    crate::node::Node,
+    async_std::{sync::RwLock, task},
+    std::{sync::Arc, time::Duration},
+};
+
+/// how often expired values are swept from the local table
+pub const EXPIRE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// remove expired values every given interval.
+/// expired values are already hidden from lookups, this only frees their memory.
+pub async fn expire_loop(node: Arc<RwLock<Node>>, interval: Duration) {
+    loop {
+        task::sleep(interval).await;
+        match node.write().await.remove_expired() {
+            Ok(0) => {}
+            Ok(count) => println!("Removed {} expired values", count),
+            Err(e) => println!("Expired values removal fail: {}", e),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use {
+    crate::error::{Error, Result},
+    async_std::{
+        io::{Read, Write},
+        prelude::*,
+    },
+    serde::{de::DeserializeOwned, Serialize},
+};
+
+/// default upper bound of a single frame payload in bytes
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/// length of the frame header holding payload length as 32-bit big-endian integer
+pub const HEADER_SIZE: usize = 4;
+
+/// write given payload as a single frame: 4-byte big-endian length followed by the payload.
+pub async fn write_frame<W>(writer: &mut W, payload: &[u8], max_size: usize) -> Result<()>
+where
+    W: Write + Unpin,
+{
+    if payload.len() > max_size {
+        return Err(Error::FrameTooLarge(payload.len(), max_size));
+    }
+    writer
+        .write_all(&(payload.len() as u32).to_be_bytes())
+        .await?;
+    writer.write_all(payload).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// read a single frame and return its payload.
+/// returns None if the stream is closed before a new frame starts.
+pub async fn read_frame<R>(reader: &mut R, max_size: usize) -> Result<Option<Vec<u8>>>
+where
+    R: Read + Unpin,
+{
+    let mut header = [0u8; HEADER_SIZE];
+    let received = read_full(reader, &mut header).await?;
+    if received == 0 {
+        return Ok(None);
+    }
+    if received < HEADER_SIZE {
+        return Err(Error::IncompleteFrame(received, HEADER_SIZE));
+    }
+
+    let len = u32::from_be_bytes(header) as usize;
+    if len > max_size {
+        return Err(Error::FrameTooLarge(len, max_size));
+    }
+    let mut payload = vec![0u8; len];
+    let received = read_full(reader, &mut payload).await?;
+    if received < len {
+        return Err(Error::IncompleteFrame(received, len));
+    }
+    Ok(Some(payload))
+}
+
+/// serialize given message as JSON and write it as a single frame.
+pub async fn write_message<W, T>(writer: &mut W, message: &T, max_size: usize) -> Result<()>
+where
+    W: Write + Unpin,
+    T: Serialize,
+{
+    let payload = serde_json::to_vec(message)?;
+    write_frame(writer, &payload, max_size).await
+}
+
+/// read a single frame and deserialize its JSON payload.
+/// returns None if the stream is closed before a new frame starts.
+pub async fn read_message<R, T>(reader: &mut R, max_size: usize) -> Result<Option<T>>
+where
+    R: Read + Unpin,
+    T: DeserializeOwned,
+{
+    match read_frame(reader, max_size).await? {
+        Some(payload) => Ok(Some(serde_json::from_slice(&payload)?)),
+        None => Ok(None),
+    }
+}
+
+/// fill given buffer until it is full or the stream is closed, returning bytes read.
+async fn read_full<R>(reader: &mut R, buf: &mut [u8]) -> Result<usize>
+where
+    R: Read + Unpin,
+{
+    let mut received = 0;
+    while received < buf.len() {
+        let count = reader.read(&mut buf[received..]).await?;
+        if count == 0 {
+            break;
+        }
+        received += count;
+    }
+    Ok(received)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::{io::Cursor, task::block_on};
+
+    #[test]
+    fn test_write_and_read_frame() {
+        let mut buf = Cursor::new(Vec::new());
+        block_on(write_frame(&mut buf, b"hello", DEFAULT_MAX_FRAME_SIZE)).unwrap();
+        block_on(write_frame(&mut buf, b"world", DEFAULT_MAX_FRAME_SIZE)).unwrap();
+        assert_eq!(&buf.get_ref()[..HEADER_SIZE], &[0, 0, 0, 5]);
+
+        buf.set_position(0);
+        let first = block_on(read_frame(&mut buf, DEFAULT_MAX_FRAME_SIZE)).unwrap();
+        let second = block_on(read_frame(&mut buf, DEFAULT_MAX_FRAME_SIZE)).unwrap();
+        let end = block_on(read_frame(&mut buf, DEFAULT_MAX_FRAME_SIZE)).unwrap();
+        assert_eq!(first, Some(b"hello".to_vec()));
+        assert_eq!(second, Some(b"world".to_vec()));
+        assert_eq!(end, None);
+    }
+
+    #[test]
+    fn test_frame_larger_than_32_bytes() {
+        let payload = vec![7u8; 1000];
+        let mut buf = Cursor::new(Vec::new());
+        block_on(write_frame(&mut buf, &payload, DEFAULT_MAX_FRAME_SIZE)).unwrap();
+        buf.set_position(0);
+        let read = block_on(read_frame(&mut buf, DEFAULT_MAX_FRAME_SIZE)).unwrap();
+        assert_eq!(read, Some(payload));
+    }
+
+    #[test]
+    fn test_write_oversized_frame() {
+        let mut buf = Cursor::new(Vec::new());
+        let res = block_on(write_frame(&mut buf, b"hello", 4));
+        assert!(matches!(res, Err(Error::FrameTooLarge(5, 4))));
+        assert!(buf.get_ref().is_empty());
+    }
+
+    #[test]
+    fn test_read_oversized_frame() {
+        let mut buf = Cursor::new(Vec::new());
+        block_on(write_frame(&mut buf, b"hello", DEFAULT_MAX_FRAME_SIZE)).unwrap();
+        buf.set_position(0);
+        let res = block_on(read_frame(&mut buf, 4));
+        assert!(matches!(res, Err(Error::FrameTooLarge(5, 4))));
+    }
+
+    #[test]
+    fn test_read_incomplete_frame() {
+        let mut buf = Cursor::new(vec![0, 0, 0, 5, b'h', b'e']);
+        let res = block_on(read_frame(&mut buf, DEFAULT_MAX_FRAME_SIZE));
+        assert!(matches!(res, Err(Error::IncompleteFrame(2, 5))));
+
+        let mut buf = Cursor::new(vec![0, 0]);
+        let res = block_on(read_frame(&mut buf, DEFAULT_MAX_FRAME_SIZE));
+        assert!(matches!(res, Err(Error::IncompleteFrame(2, HEADER_SIZE))));
+    }
+
+    #[test]
+    fn test_write_and_read_message() {
+        let mut buf = Cursor::new(Vec::new());
+        let message = vec!["a".to_owned(), "b".to_owned()];
+        block_on(write_message(&mut buf, &message, DEFAULT_MAX_FRAME_SIZE)).unwrap();
+        buf.set_position(0);
+        let read: Option<Vec<String>> =
+            block_on(read_message(&mut buf, DEFAULT_MAX_FRAME_SIZE)).unwrap();
+        assert_eq!(read, Some(message));
+    }
+}
@@ -0,0 +1,558 @@
+use {
+    crate::{
+        admin::Status,
+        error::Result,
+        in_memory_hash_table::DEFAULT_TTL,
+        key::Key,
+        lookup::{get, lookup_nodes},
+        node::Node,
+        replication::put,
+    },
+    async_std::{
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, TcpStream},
+        prelude::*,
+        sync::RwLock,
+        task,
+    },
+    serde::Serialize,
+    std::{sync::Arc, time::Duration},
+};
+
+/// upper bound of the request line and headers of an HTTP request in bytes
+pub const MAX_HEAD_SIZE: usize = 8 * 1024;
+
+/// upper bound of a signed STORE request or VALUE response without its value, with the longest
+/// addresses and ttl
+const MESSAGE_OVERHEAD: usize = 2 * 1024;
+
+/// largest value whose STORE request and VALUE response fit in frames of given size.
+/// values are serialized as JSON arrays of numbers, taking up to 4 bytes per byte, e.g. `255,`
+pub fn max_value_size(max_frame_size: usize) -> usize {
+    max_frame_size.saturating_sub(MESSAGE_OVERHEAD) / 4
+}
+
+/// accept HTTP connections on given listener and serve a single request on each of them.
+/// request bodies larger than max_body_size are rejected, see `max_value_size`.
+///
+/// - `PUT /kv/{key}[?ttl=<seconds>]` stores the raw body at the k closest nodes to the key
+/// - `GET /kv/{key}` returns the raw value stored for the key
+/// - `GET /nodes/{id}` returns the k closest nodes to given hex id as JSON
+/// - `GET /status` returns id, version, uptime and request counters of this node as JSON
+///
+/// `{key}` is hashed into a key the same way as in `anonymous_client`.
+pub async fn serve_http(
+    listener: TcpListener,
+    node: Arc<RwLock<Node>>,
+    max_body_size: usize,
+) -> Result<()> {
+    let mut incoming = listener.incoming();
+    while let Some(Ok(stream)) = incoming.next().await {
+        let node = node.clone();
+        task::spawn(async move {
+            if let Err(e) = handle_connection(stream, node, max_body_size).await {
+                println!("HTTP connection fail: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    node: Arc<RwLock<Node>>,
+    max_body_size: usize,
+) -> Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let res = match read_request(&mut reader, &mut &stream, max_body_size).await {
+        Ok(req) => {
+            let res = handle_http_request(&node, &req).await;
+            println!("HTTP {} {} {}", req.method, req.path, res.status);
+            res
+        }
+        Err(res) => res,
+    };
+    write_response(&mut &stream, &res).await
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    query: Option<String>,
+    body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// value of given query parameter
+    fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .as_deref()?
+            .split('&')
+            .filter_map(|pair| {
+                let mut parts = pair.splitn(2, '=');
+                Some((parts.next()?, parts.next().unwrap_or("")))
+            })
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| v)
+    }
+}
+
+struct HttpResponse {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// value returned as is
+    fn raw(body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/octet-stream",
+            body,
+        }
+    }
+
+    fn json<T: Serialize>(status: u16, body: &T) -> Self {
+        match serde_json::to_vec(body) {
+            Ok(body) => Self {
+                status,
+                content_type: "application/json",
+                body,
+            },
+            Err(e) => Self::error(500, &format!("response serialize fail: {}", e)),
+        }
+    }
+
+    fn error(status: u16, msg: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", msg).into_bytes(),
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        _ => "",
+    }
+}
+
+/// read a request head and its body of Content-Length bytes.
+/// a client expecting `100 Continue` is told to send the body once the head is accepted.
+/// returns the response to send back if the request can't be read.
+async fn read_request<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    max_body_size: usize,
+) -> std::result::Result<HttpRequest, HttpResponse>
+where
+    R: BufRead + Unpin,
+    W: Write + Unpin,
+{
+    let head = read_head(reader).await?;
+    let mut lines = head.lines();
+    let request_line = lines.next().unwrap_or("");
+    let (method, target) = match request_line.split(' ').collect::<Vec<_>>()[..] {
+        [method, target, version] if version.starts_with("HTTP/1.") => (method, target),
+        _ => return Err(HttpResponse::error(400, "malformed request line")),
+    };
+
+    let mut content_length = 0;
+    let mut expect_continue = false;
+    for line in lines.filter(|l| !l.is_empty()) {
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let value = match parts.next() {
+            Some(value) => value.trim(),
+            None => return Err(HttpResponse::error(400, "malformed header")),
+        };
+        match name.as_str() {
+            "content-length" => {
+                content_length = value
+                    .parse()
+                    .map_err(|_| HttpResponse::error(400, "invalid Content-Length"))?
+            }
+            "transfer-encoding" => {
+                return Err(HttpResponse::error(
+                    501,
+                    "only bodies with a Content-Length are supported",
+                ))
+            }
+            "expect" => expect_continue = value.eq_ignore_ascii_case("100-continue"),
+            _ => {}
+        }
+    }
+    if content_length > max_body_size {
+        return Err(HttpResponse::error(
+            413,
+            &format!("body is larger than {} bytes", max_body_size),
+        ));
+    }
+
+    if expect_continue && content_length > 0 {
+        let continue_line = format!("HTTP/1.1 100 {}\r\n\r\n", reason(100));
+        writer
+            .write_all(continue_line.as_bytes())
+            .await
+            .map_err(|_| HttpResponse::error(400, "connection closed"))?;
+    }
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .await
+        .map_err(|_| HttpResponse::error(400, "body shorter than Content-Length"))?;
+
+    let mut target = target.splitn(2, '?');
+    Ok(HttpRequest {
+        method: method.to_owned(),
+        path: target.next().unwrap_or("").to_owned(),
+        query: target.next().map(str::to_owned),
+        body,
+    })
+}
+
+/// read request line and headers up to the empty line ending them
+async fn read_head<R: BufRead + Unpin>(
+    reader: &mut R,
+) -> std::result::Result<String, HttpResponse> {
+    let mut head = Vec::new();
+    let mut limited = reader.take(MAX_HEAD_SIZE as u64);
+    loop {
+        let start = head.len();
+        let read = limited
+            .read_until(b'\n', &mut head)
+            .await
+            .map_err(|_| HttpResponse::error(400, "connection closed"))?;
+        if read == 0 {
+            if head.len() >= MAX_HEAD_SIZE {
+                return Err(HttpResponse::error(431, "request head too large"));
+            }
+            return Err(HttpResponse::error(400, "incomplete request head"));
+        }
+        if &head[start..] == b"\r\n" || &head[start..] == b"\n" {
+            break;
+        }
+    }
+    String::from_utf8(head).map_err(|_| HttpResponse::error(400, "request head is not UTF-8"))
+}
+
+async fn write_response<W: Write + Unpin>(writer: &mut W, res: &HttpResponse) -> Result<()> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        res.status,
+        reason(res.status),
+        res.content_type,
+        res.body.len()
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(&res.body).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// decode `%XX` escapes of given path segment
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+async fn handle_http_request(node: &Arc<RwLock<Node>>, req: &HttpRequest) -> HttpResponse {
+    let segments: Option<Vec<String>> = req
+        .path
+        .trim_start_matches('/')
+        .split('/')
+        .map(percent_decode)
+        .collect();
+    let segments = match segments {
+        Some(segments) => segments,
+        None => return HttpResponse::error(400, "invalid percent-encoding in path"),
+    };
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    match (req.method.as_str(), &segments[..]) {
+        ("PUT", ["kv", key]) => put_value(node, key, req).await,
+        ("GET", ["kv", key]) => get_value(node, key).await,
+        ("GET", ["nodes", id]) => find_nodes(node, id).await,
+        ("GET", ["status"]) => HttpResponse::json(200, &Status::of(&*node.read().await)),
+        (_, ["kv", _]) | (_, ["nodes", _]) | (_, ["status"]) => {
+            HttpResponse::error(405, "method not allowed")
+        }
+        _ => HttpResponse::error(404, "not found"),
+    }
+}
+
+async fn put_value(node: &Arc<RwLock<Node>>, key: &str, req: &HttpRequest) -> HttpResponse {
+    let ttl = match req.query_param("ttl") {
+        Some(s) => match s.parse() {
+            Ok(secs) => Duration::from_secs(secs),
+            Err(_) => return HttpResponse::error(400, &format!("invalid ttl: {}", s)),
+        },
+        None => DEFAULT_TTL,
+    };
+    let min_replicas = node.read().await.get_config().get_min_replicas();
+    match put(node, key.into(), req.body.clone(), ttl, min_replicas).await {
+        Ok(res) => {
+            let body = serde_json::json!({ "stored": res.stored(), "min_replicas": min_replicas });
+            HttpResponse::json(if res.is_success() { 200 } else { 502 }, &body)
+        }
+        Err(e) => HttpResponse::error(500, &format!("put failed: {}", e)),
+    }
+}
+
+async fn get_value(node: &Arc<RwLock<Node>>, key: &str) -> HttpResponse {
+    match get(node, &key.into()).await {
+        Ok(Some(res)) => HttpResponse::raw(res.get_value().clone()),
+        Ok(None) => HttpResponse::error(404, "value not found"),
+        Err(e) => HttpResponse::error(500, &format!("get failed: {}", e)),
+    }
+}
+
+async fn find_nodes(node: &Arc<RwLock<Node>>, id: &str) -> HttpResponse {
+    let id = match Key::from_hex(id) {
+        Some(id) => id,
+        None => return HttpResponse::error(400, "node id must be 40 hex characters"),
+    };
+    match lookup_nodes(node, &id).await {
+        Ok(nodes) => HttpResponse::json(200, &nodes),
+        Err(e) => HttpResponse::error(500, &format!("lookup failed: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            config::Config, identity::Identity, node::NodeInfo, request::Request, rpc::Rpc,
+            server::serve,
+        },
+        async_std::task::block_on,
+        std::net::{Shutdown, SocketAddr},
+    };
+
+    fn parse(raw: &[u8], max_body_size: usize) -> (std::result::Result<HttpRequest, u16>, Vec<u8>) {
+        let mut written = Vec::new();
+        let res = block_on(read_request(&mut &raw[..], &mut written, max_body_size));
+        (res.map_err(|res| res.status), written)
+    }
+
+    #[test]
+    fn test_read_request() {
+        let (req, written) = parse(
+            b"PUT /kv/k1?ttl=60 HTTP/1.1\r\nHost: localhost\r\ncontent-length: 5\r\n\r\nvalue",
+            1024,
+        );
+        let req = req.ok().unwrap();
+        assert_eq!(req.method, "PUT");
+        assert_eq!(req.path, "/kv/k1");
+        assert_eq!(req.query_param("ttl"), Some("60"));
+        assert_eq!(req.query_param("other"), None);
+        assert_eq!(req.body, b"value".to_vec());
+        assert!(written.is_empty());
+
+        // client waiting for the server to accept the body
+        let (req, written) = parse(
+            b"PUT /kv/k1 HTTP/1.1\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\r\nvalue",
+            1024,
+        );
+        assert_eq!(req.ok().unwrap().body, b"value".to_vec());
+        assert_eq!(written, b"HTTP/1.1 100 Continue\r\n\r\n".to_vec());
+    }
+
+    #[test]
+    fn test_invalid_request() {
+        assert_eq!(parse(b"GET /status\r\n\r\n", 1024).0.err(), Some(400));
+        assert_eq!(parse(b"GET /status HTTP/1.1\r\n", 1024).0.err(), Some(400));
+        assert_eq!(
+            parse(b"PUT /kv/k1 HTTP/1.1\r\nContent-Length: 5\r\n\r\nval", 1024)
+                .0
+                .err(),
+            Some(400)
+        );
+        assert_eq!(
+            parse(b"PUT /kv/k1 HTTP/1.1\r\nContent-Length: 5\r\n\r\nvalue", 4)
+                .0
+                .err(),
+            Some(413)
+        );
+        assert_eq!(
+            parse(
+                b"PUT /kv/k1 HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n",
+                1024
+            )
+            .0
+            .err(),
+            Some(501)
+        );
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_HEAD_SIZE));
+        assert_eq!(parse(long.as_bytes(), 1024).0.err(), Some(431));
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%20b%2Fc"), Some("a b/c".to_owned()));
+        assert_eq!(percent_decode("plain"), Some("plain".to_owned()));
+        assert_eq!(percent_decode("bad%2"), None);
+        assert_eq!(percent_decode("bad%zz"), None);
+    }
+
+    #[test]
+    fn test_max_value_size() {
+        let max_frame_size = 16 * 1024;
+        let size = max_value_size(max_frame_size);
+        assert!(size > 3 * 1024);
+
+        // worst case STORE request: longest addresses, ttl and serialized bytes
+        let v6: SocketAddr = "[ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff]:65535"
+            .parse()
+            .unwrap();
+        let v4: SocketAddr = "255.255.255.255:65535".parse().unwrap();
+        let identity = Identity::generate().unwrap().0;
+        let from = NodeInfo::with_alt_host(v6, Some(v4), identity.get_id().clone());
+        let rpc = Rpc::Store(
+            Key::new([255; 20]),
+            vec![255; size],
+            Some(Duration::new(u64::MAX, 999_999_999)),
+        );
+        let mut req = Request::new(Some(from), rpc, v6);
+        req.sign(&identity);
+        assert!(serde_json::to_vec(&req).unwrap().len() <= max_frame_size);
+    }
+
+    /// send given raw request to given address and return status and body of the response
+    async fn request(addr: SocketAddr, raw: &[u8]) -> (u16, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(raw).await.unwrap();
+        let mut res = Vec::new();
+        stream.read_to_end(&mut res).await.unwrap();
+        let _ = stream.shutdown(Shutdown::Both);
+
+        let end = res.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let status_line = String::from_utf8_lossy(&res[..end]).to_string();
+        let status = status_line.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, res[end + 4..].to_vec())
+    }
+
+    #[test]
+    fn test_gateway_over_tcp() {
+        block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            // single node network, where this node is the only replica
+            let config = Config::builder().min_replicas(1).build().unwrap();
+            let identity = Identity::generate().unwrap().0;
+            let node = Node::with_config(addr, identity, config).unwrap();
+            let id = node.get_id().clone();
+            let node = Arc::new(RwLock::new(node));
+            task::spawn(serve_http(listener, node, 1024));
+
+            let (status, _) = request(addr, b"GET /kv/k1 HTTP/1.1\r\n\r\n").await;
+            assert_eq!(status, 404);
+
+            let value = [0u8, 159, 146, 150, 255];
+            let mut put = b"PUT /kv/k1 HTTP/1.1\r\nContent-Length: 5\r\n\r\n".to_vec();
+            put.extend_from_slice(&value);
+            let (status, body) = request(addr, &put).await;
+            assert_eq!(status, 200);
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["stored"], 1);
+
+            // binary value is returned as is
+            let (status, body) = request(addr, b"GET /kv/k1 HTTP/1.1\r\n\r\n").await;
+            assert_eq!(status, 200);
+            assert_eq!(body, value.to_vec());
+
+            let (status, body) = request(addr, b"GET /status HTTP/1.1\r\n\r\n").await;
+            assert_eq!(status, 200);
+            let status: Status = serde_json::from_slice(&body).unwrap();
+            assert_eq!(status.get_id(), &id);
+
+            let nodes = format!("GET /nodes/{} HTTP/1.1\r\n\r\n", id.to_hex());
+            assert_eq!(request(addr, nodes.as_bytes()).await.0, 200);
+            assert_eq!(
+                request(addr, b"GET /nodes/k1 HTTP/1.1\r\n\r\n").await.0,
+                400
+            );
+            assert_eq!(
+                request(addr, b"DELETE /kv/k1 HTTP/1.1\r\n\r\n").await.0,
+                405
+            );
+            assert_eq!(request(addr, b"GET /other HTTP/1.1\r\n\r\n").await.0, 404);
+        });
+    }
+
+    #[test]
+    fn test_put_near_max_value_size() {
+        block_on(async {
+            let max_frame_size = 16 * 1024;
+            let config = Config::builder()
+                .min_replicas(2)
+                .max_frame_size(max_frame_size)
+                .build()
+                .unwrap();
+            // remote replica reached over TCP with the same frame size
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let identity = Identity::generate().unwrap().0;
+            let remote =
+                Node::with_config(listener.local_addr().unwrap(), identity, config.clone())
+                    .unwrap();
+            let remote_info = remote.get_info();
+            let remote = Arc::new(RwLock::new(remote));
+            task::spawn(serve(listener, remote.clone(), max_frame_size));
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let identity = Identity::generate().unwrap().0;
+            let mut node = Node::with_config(addr, identity, config).unwrap();
+            node.update_bucket(remote_info);
+            let node = Arc::new(RwLock::new(node));
+            let max_body_size = max_value_size(max_frame_size);
+            task::spawn(serve_http(listener, node, max_body_size));
+
+            // every byte of the value takes 4 bytes in the STORE request
+            let value = vec![255u8; max_body_size];
+            let mut put = format!(
+                "PUT /kv/k1 HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+                value.len()
+            )
+            .into_bytes();
+            put.extend_from_slice(&value);
+            let (status, body) = request(addr, &put).await;
+            assert_eq!(status, 200);
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["stored"], 2);
+            assert_eq!(remote.read().await.find_value(&"k1".into()), Some(value));
+
+            // larger values are rejected before the body is sent
+            let put = format!(
+                "PUT /kv/k1 HTTP/1.1\r\nContent-Length: {}\r\nExpect: 100-continue\r\n\r\n",
+                max_body_size + 1
+            );
+            assert_eq!(request(addr, put.as_bytes()).await.0, 413);
+        });
+    }
+}
@@ -0,0 +1,201 @@
+use {
+    crate::{
+        error::{Error, Result},
+        key::Key,
+    },
+    ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+    },
+    serde::{Deserialize, Serialize},
+    std::{
+        fmt,
+        fs::{self, OpenOptions},
+        io::{self, Write},
+        path::Path,
+    },
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+/// name of the file node keypair is saved to in the data directory
+pub const IDENTITY_FILE: &str = "identity";
+
+/// Ed25519 keypair of a node. node id is derived from the public key, so that nobody can claim
+/// an id without holding its private key.
+pub struct Identity {
+    keypair: Ed25519KeyPair,
+    id: Key,
+}
+
+impl Identity {
+    /// generate a new random keypair, returning it with its PKCS#8 document to save it
+    pub fn generate() -> Result<(Self, Vec<u8>)> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| Error::InvalidIdentity("failed to generate keypair".to_owned()))?;
+        let identity = Self::from_pkcs8(pkcs8.as_ref())?;
+        Ok((identity, pkcs8.as_ref().to_vec()))
+    }
+
+    /// keypair from a PKCS#8 document given by `generate`
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self> {
+        let keypair = Ed25519KeyPair::from_pkcs8(pkcs8)
+            .map_err(|e| Error::InvalidIdentity(format!("invalid keypair: {}", e)))?;
+        Ok(Self::from_keypair(keypair))
+    }
+
+    /// keypair derived from given 32-byte seed, giving the same id every time
+    pub fn from_seed(seed: &[u8]) -> Result<Self> {
+        let keypair = Ed25519KeyPair::from_seed_unchecked(seed)
+            .map_err(|e| Error::InvalidIdentity(format!("invalid seed: {}", e)))?;
+        Ok(Self::from_keypair(keypair))
+    }
+
+    fn from_keypair(keypair: Ed25519KeyPair) -> Self {
+        let id = Key::from_public_key(keypair.public_key().as_ref());
+        Self { keypair, id }
+    }
+
+    pub fn get_id(&self) -> &Key {
+        &self.id
+    }
+
+    /// sign given message with the private key
+    pub fn sign(&self, msg: &[u8]) -> Signature {
+        Signature {
+            public_key: self.keypair.public_key().as_ref().to_vec(),
+            signature: self.keypair.sign(msg).as_ref().to_vec(),
+        }
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Identity({:?})", self.id)
+    }
+}
+
+/// signature of a message with the public key to check it against
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Signature {
+    public_key: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl Signature {
+    /// check that the signing key is the one of node with given id, and that it signed given
+    /// message
+    pub fn verify(&self, id: &Key, msg: &[u8]) -> Result<()> {
+        if &Key::from_public_key(&self.public_key) != id {
+            return Err(Error::InvalidSignature(format!(
+                "signing key doesn't belong to {}",
+                id.to_hex()
+            )));
+        }
+        UnparsedPublicKey::new(&ED25519, &self.public_key)
+            .verify(msg, &self.signature)
+            .map_err(|_| Error::InvalidSignature(format!("message not signed by {}", id.to_hex())))
+    }
+}
+
+/// bytes covered by the signature of given message: the message serialized with its
+/// `signature` field cleared. keys of the serialized objects are sorted, so that the receiver
+/// gets the same bytes back from the deserialized message.
+pub fn signed_bytes<T: Serialize>(msg: &T) -> Vec<u8> {
+    let mut value = serde_json::to_value(msg).expect("message is serializable");
+    value["signature"] = serde_json::Value::Null;
+    serde_json::to_vec(&value).expect("message is serializable")
+}
+
+/// load node keypair from given file, or generate one and save it there if the file doesn't
+/// exist, so that the node keeps its id across restarts.
+pub fn load_or_create_identity<P: AsRef<Path>>(path: P) -> Result<Identity> {
+    let path = path.as_ref();
+    match fs::read(path) {
+        Ok(pkcs8) => Identity::from_pkcs8(&pkcs8).map_err(|_| {
+            Error::InvalidIdentity(format!("file {} doesn't hold a keypair", path.display()))
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let (identity, pkcs8) = Identity::generate()?;
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            // only the owner may read the private key. a file left by an interrupted write is
+            // removed first, since an existing file keeps its permissions
+            let tmp = path.with_extension("tmp");
+            match fs::remove_file(&tmp) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            let mut file = options.open(&tmp)?;
+            file.write_all(&pkcs8)?;
+            file.sync_all()?;
+            fs::rename(&tmp, path)?;
+            Ok(identity)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::env};
+
+    #[test]
+    fn test_identity_reused() {
+        let path = env::temp_dir().join(format!("kadrs_identity_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let id = load_or_create_identity(&path).unwrap().get_id().clone();
+        assert_eq!(load_or_create_identity(&path).unwrap().get_id(), &id);
+        fs::remove_file(&path).unwrap();
+        assert_ne!(load_or_create_identity(&path).unwrap().get_id(), &id);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_identity_file_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = env::temp_dir().join(format!("kadrs_private_identity_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        // leftover of an interrupted write readable by anyone
+        fs::write(path.with_extension("tmp"), "partial").unwrap();
+        fs::set_permissions(
+            path.with_extension("tmp"),
+            fs::Permissions::from_mode(0o644),
+        )
+        .unwrap();
+
+        load_or_create_identity(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(!path.with_extension("tmp").exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_identity() {
+        let path = env::temp_dir().join(format!("kadrs_invalid_identity_{}", std::process::id()));
+        fs::write(&path, "not a keypair").unwrap();
+        let res = load_or_create_identity(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(res, Err(Error::InvalidIdentity(_))));
+    }
+
+    #[test]
+    fn test_signature() {
+        let identity = Identity::from_seed(&[1; 32]).unwrap();
+        let other = Identity::from_seed(&[2; 32]).unwrap();
+        let signature = identity.sign(b"msg");
+        assert!(signature.verify(identity.get_id(), b"msg").is_ok());
+        assert!(signature.verify(identity.get_id(), b"other msg").is_err());
+        // valid signature, but claiming another id
+        assert!(signature.verify(other.get_id(), b"msg").is_err());
+    }
+}
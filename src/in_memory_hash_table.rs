@@ -1,7 +1,45 @@
-use {crate::key::Key, std::collections::HashMap};
+use {
+    crate::{error::Result, key::Key, storage::Storage},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::HashMap,
+        time::{Duration, SystemTime},
+    },
+};
+
+/// time to live of a value when the STORE doesn't specify one
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// stored value with the time it was stored at and how long it lives from then
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    value: Vec<u8>,
+    stored_at: SystemTime,
+    ttl: Duration,
+}
+
+impl Entry {
+    /// entry stored now
+    pub fn new(value: Vec<u8>, ttl: Duration) -> Self {
+        Self {
+            value,
+            stored_at: SystemTime::now(),
+            ttl,
+        }
+    }
+
+    pub fn get_value(&self) -> &Vec<u8> {
+        &self.value
+    }
+
+    /// an entry whose stored_at is in the future, e.g. after a clock change, is not expired
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        matches!(now.duration_since(self.stored_at), Ok(age) if age >= self.ttl)
+    }
+}
 
 pub struct Table {
-    inner: HashMap<Key, Vec<u8>>,
+    inner: HashMap<Key, Entry>,
 }
 
 impl Table {
@@ -11,12 +49,74 @@ impl Table {
         }
     }
 
-    pub fn get(&self, key: &Key) -> Option<&Vec<u8>> {
-        self.inner.get(key)
+    /// insert given entry as is, returning the previous one even if it has expired
+    pub fn insert(&mut self, key: Key, entry: Entry) -> Option<Entry> {
+        self.inner.insert(key, entry)
     }
 
-    pub fn put(&mut self, key: Key, value: Vec<u8>) -> Option<Vec<u8>> {
-        self.inner.insert(key, value)
+    /// remove every expired entry, returning their keys
+    pub fn drain_expired(&mut self) -> Vec<Key> {
+        let now = SystemTime::now();
+        let expired: Vec<Key> = self
+            .inner
+            .iter()
+            .filter(|(_, e)| e.is_expired(now))
+            .map(|(k, _)| k.clone())
+            .collect();
+        for k in expired.iter() {
+            self.inner.remove(k);
+        }
+        expired
+    }
+}
+
+impl Storage for Table {
+    fn get(&self, key: &Key) -> Option<&Vec<u8>> {
+        let now = SystemTime::now();
+        self.inner
+            .get(key)
+            .filter(|e| !e.is_expired(now))
+            .map(|e| &e.value)
+    }
+
+    fn put(&mut self, key: Key, value: Vec<u8>, ttl: Duration) -> Result<Option<Vec<u8>>> {
+        let entry = Entry::new(value, ttl);
+        let now = entry.stored_at;
+        Ok(self
+            .insert(key, entry)
+            .filter(|e| !e.is_expired(now))
+            .map(|e| e.value))
+    }
+
+    fn stored_before(&self, age: Duration) -> Vec<(Key, Vec<u8>, Duration)> {
+        let now = SystemTime::now();
+        self.inner
+            .iter()
+            .filter_map(|(k, e)| {
+                let elapsed = now.duration_since(e.stored_at).ok()?;
+                let remaining = e.ttl.checked_sub(elapsed)?;
+                if elapsed <= age || remaining == Duration::from_secs(0) {
+                    return None;
+                }
+                Some((k.clone(), e.value.clone(), remaining))
+            })
+            .collect()
+    }
+
+    fn entries(&self) -> Vec<(Key, usize, Duration)> {
+        let now = SystemTime::now();
+        self.inner
+            .iter()
+            .filter(|(_, e)| !e.is_expired(now))
+            .map(|(k, e)| {
+                let elapsed = now.duration_since(e.stored_at).unwrap_or_default();
+                (k.clone(), e.value.len(), e.ttl - elapsed)
+            })
+            .collect()
+    }
+
+    fn remove_expired(&mut self) -> Result<usize> {
+        Ok(self.drain_expired().len())
     }
 }
 
@@ -29,9 +129,77 @@ mod tests {
         let key: Key = "k1".into();
         let value: Vec<u8> = (&b"val"[..]).into();
         let mut table = Table::new();
-        let put_result = table.put(key.clone(), value.clone());
+        let put_result = table.put(key.clone(), value.clone(), DEFAULT_TTL).unwrap();
         assert_eq!(put_result, None);
         let get_result = table.get(&key).unwrap();
         assert_eq!(get_result, &value);
     }
+
+    #[test]
+    fn test_expired_value_hidden() {
+        let key: Key = "k1".into();
+        let mut table = Table::new();
+        table
+            .put(key.clone(), b"val".to_vec(), Duration::from_secs(0))
+            .unwrap();
+        assert_eq!(table.get(&key), None);
+        assert_eq!(
+            table
+                .put(key.clone(), b"val2".to_vec(), DEFAULT_TTL)
+                .unwrap(),
+            None
+        );
+        assert_eq!(table.get(&key), Some(&b"val2".to_vec()));
+    }
+
+    #[test]
+    fn test_stored_before() {
+        let mut table = Table::new();
+        table
+            .put("k1".into(), b"val".to_vec(), DEFAULT_TTL)
+            .unwrap();
+        table
+            .put("k2".into(), b"val".to_vec(), Duration::from_secs(0))
+            .unwrap();
+        assert!(table.stored_before(Duration::from_secs(60)).is_empty());
+
+        std::thread::sleep(Duration::from_millis(10));
+        let entries = table.stored_before(Duration::from_millis(5));
+        assert_eq!(entries.len(), 1);
+        let (key, value, remaining) = &entries[0];
+        assert_eq!(key, &"k1".into());
+        assert_eq!(value, &b"val".to_vec());
+        assert!(remaining < &DEFAULT_TTL);
+    }
+
+    #[test]
+    fn test_entries() {
+        let mut table = Table::new();
+        table
+            .put("k1".into(), b"val".to_vec(), DEFAULT_TTL)
+            .unwrap();
+        table
+            .put("k2".into(), b"val".to_vec(), Duration::from_secs(0))
+            .unwrap();
+        let entries = table.entries();
+        assert_eq!(entries.len(), 1);
+        let (key, size, remaining) = &entries[0];
+        assert_eq!(key, &"k1".into());
+        assert_eq!(*size, 3);
+        assert!(remaining <= &DEFAULT_TTL);
+    }
+
+    #[test]
+    fn test_remove_expired() {
+        let mut table = Table::new();
+        table
+            .put("k1".into(), b"val".to_vec(), Duration::from_secs(0))
+            .unwrap();
+        table
+            .put("k2".into(), b"val".to_vec(), DEFAULT_TTL)
+            .unwrap();
+        assert_eq!(table.remove_expired().unwrap(), 1);
+        assert_eq!(table.remove_expired().unwrap(), 0);
+        assert!(table.get(&"k2".into()).is_some());
+    }
 }
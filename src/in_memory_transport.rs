@@ -0,0 +1,600 @@
+use {
+    crate::{
+        error::Result, node::Node, request::Request, response::Response, server::handle_request,
+        transport::Transport,
+    },
+    async_std::{future, sync::RwLock},
+    async_trait::async_trait,
+    std::{
+        collections::HashMap,
+        io,
+        net::SocketAddr,
+        sync::{Arc, Mutex, Weak},
+        time::Duration,
+    },
+};
+
+/// set of nodes reachable from each other through `InMemoryTransport` without any socket.
+/// nodes are kept as weak references, so the network doesn't keep dropped nodes alive.
+#[derive(Clone, Default)]
+pub struct InMemoryNetwork {
+    nodes: Arc<Mutex<HashMap<SocketAddr, Weak<RwLock<Node>>>>>,
+}
+
+impl InMemoryNetwork {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// make given node reachable at given address
+    pub fn register(&self, host: SocketAddr, node: &Arc<RwLock<Node>>) {
+        self.nodes
+            .lock()
+            .unwrap()
+            .insert(host, Arc::downgrade(node));
+    }
+
+    /// make node at given address unreachable, as if it went offline
+    pub fn unregister(&self, host: &SocketAddr) {
+        self.nodes.lock().unwrap().remove(host);
+    }
+
+    /// transport delivering requests to nodes of this network
+    pub fn transport(&self) -> Arc<InMemoryTransport> {
+        self.transport_from(&[])
+    }
+
+    /// transport of a node at given addresses, which can only deliver requests to addresses of
+    /// the same families, like a host without any route to the other family.
+    /// no address means every family is reachable.
+    pub fn transport_from(&self, local: &[SocketAddr]) -> Arc<InMemoryTransport> {
+        Arc::new(InMemoryTransport {
+            network: self.clone(),
+            local: local.to_vec(),
+        })
+    }
+
+    fn get(&self, host: &SocketAddr) -> Option<Arc<RwLock<Node>>> {
+        self.nodes.lock().unwrap().get(host).and_then(Weak::upgrade)
+    }
+}
+
+/// delivers requests by handing them directly to the destination node of an `InMemoryNetwork`.
+/// requests and responses still go through serialization, as they would on the wire.
+pub struct InMemoryTransport {
+    network: InMemoryNetwork,
+    local: Vec<SocketAddr>,
+}
+
+#[async_trait]
+impl Transport for InMemoryTransport {
+    async fn send(&self, req: &Request, timeout: Duration) -> Result<Response> {
+        let host = req.get_to();
+        if !self.local.is_empty() && !self.local.iter().any(|l| l.is_ipv4() == host.is_ipv4()) {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("no route to {}", host),
+            )
+            .into());
+        }
+        let node = self.network.get(host).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("no node at {}", host),
+            )
+        })?;
+        let req: Request = serde_json::from_slice(&serde_json::to_vec(req)?)?;
+        let res = future::timeout(timeout, handle_request(&node, &req)).await?;
+        let res: Response = serde_json::from_slice(&serde_json::to_vec(&res)?)?;
+        res.verify()?;
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {
+        crate::{
+            bootstrap::bootstrap,
+            config::Config,
+            error::Error,
+            identity::Identity,
+            in_memory_hash_table::DEFAULT_TTL,
+            key::Key,
+            lookup::{get, lookup_nodes},
+            node::{update_contact, NodeInfo},
+            refresh::refresh_idle_buckets,
+            replication::{put, republish, DEFAULT_MIN_REPLICAS, REPUBLISH_ORIGINAL_INTERVAL},
+            request::RPC_TIMEOUT,
+            response::ResponseBody,
+            rpc::Rpc,
+        },
+        async_std::task::{self, block_on},
+        ring::digest::{digest, SHA256},
+        std::time::Instant,
+    };
+
+    /// small buckets, so that networks of a few dozen nodes span several buckets
+    const K: usize = 10;
+
+    fn host(i: usize) -> SocketAddr {
+        format!("127.0.0.1:{}", 3000 + i).parse().unwrap()
+    }
+
+    /// keypair of the node at given address, derived from the address to keep ids
+    /// deterministic
+    fn identity(host: SocketAddr) -> Identity {
+        let seed = digest(&SHA256, host.to_string().as_bytes());
+        Identity::from_seed(seed.as_ref()).unwrap()
+    }
+
+    fn info(i: usize) -> NodeInfo {
+        NodeInfo::new(host(i), identity(host(i)).get_id().clone())
+    }
+
+    fn host6(i: usize) -> SocketAddr {
+        format!("[::1]:{}", 3000 + i).parse().unwrap()
+    }
+
+    fn create_node(network: &InMemoryNetwork, host: SocketAddr) -> Arc<RwLock<Node>> {
+        create_dual_stack_node(network, host, None)
+    }
+
+    /// node registered at both given addresses, only able to reach their families
+    fn create_dual_stack_node(
+        network: &InMemoryNetwork,
+        host: SocketAddr,
+        alt_host: Option<SocketAddr>,
+    ) -> Arc<RwLock<Node>> {
+        let config = Config::builder().k(K).build().unwrap();
+        let mut node = Node::with_config(host, identity(host), config).unwrap();
+        let hosts: Vec<SocketAddr> = std::iter::once(host).chain(alt_host).collect();
+        if let Some(alt_host) = alt_host {
+            node.set_alt_host(alt_host);
+        }
+        node.set_transport(network.transport_from(&hosts));
+        let node = Arc::new(RwLock::new(node));
+        for host in hosts {
+            network.register(host, &node);
+        }
+        node
+    }
+
+    /// build a network of n nodes, each joining through the first one
+    async fn create_network(n: usize) -> (InMemoryNetwork, Vec<Arc<RwLock<Node>>>) {
+        let network = InMemoryNetwork::new();
+        let mut nodes = Vec::new();
+        for i in 0..n {
+            let node = create_node(&network, host(i));
+            if i > 0 {
+                update_contact(&node, info(0)).await;
+                let own_id = node.read().await.get_id().clone();
+                lookup_nodes(&node, &own_id).await.unwrap();
+            }
+            nodes.push(node);
+        }
+        (network, nodes)
+    }
+
+    /// k closest nodes among all nodes of the network except the one at given index
+    fn expected_closest(n: usize, except: usize, target: &Key) -> Vec<NodeInfo> {
+        let mut all: Vec<NodeInfo> = (0..n).filter(|i| *i != except).map(info).collect();
+        all.sort_by_key(|info| info.get_id().distance(target));
+        all.truncate(K);
+        all
+    }
+
+    #[test]
+    fn test_send_to_registered_node() {
+        block_on(async {
+            let network = InMemoryNetwork::new();
+            let _node = create_node(&network, host(0));
+            let req = Request::new(None, Rpc::Ping, host(0));
+            let res = network.transport().send(&req, RPC_TIMEOUT).await.unwrap();
+            assert_eq!(res.get_request_id(), Some(req.get_id()));
+            assert!(matches!(res.get_body(), Some(ResponseBody::PONG)));
+        });
+    }
+
+    #[test]
+    fn test_send_to_unregistered_node() {
+        block_on(async {
+            let network = InMemoryNetwork::new();
+            let _node = create_node(&network, host(0));
+            network.unregister(&host(0));
+            let req = Request::new(None, Rpc::Ping, host(0));
+            assert!(network.transport().send(&req, RPC_TIMEOUT).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_send_to_unreachable_family() {
+        block_on(async {
+            let network = InMemoryNetwork::new();
+            let _node = create_node(&network, host6(0));
+            let req = Request::new(None, Rpc::Ping, host6(0));
+            let transport = network.transport_from(&[host(1)]);
+            assert!(transport.send(&req, RPC_TIMEOUT).await.is_err());
+            assert!(network.transport().send(&req, RPC_TIMEOUT).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_lookup_prefers_reachable_family() {
+        block_on(async {
+            // dual-stack nodes advertising their IPv6 address first, and IPv4-only nodes
+            let n = 20;
+            let network = InMemoryNetwork::new();
+            let mut nodes = Vec::new();
+            let mut infos = Vec::new();
+            for i in 0..n {
+                let node = if i % 2 == 0 {
+                    create_dual_stack_node(&network, host6(i), Some(host(i)))
+                } else {
+                    create_node(&network, host(i))
+                };
+                infos.push(node.read().await.get_info());
+                if i > 0 {
+                    update_contact(&node, infos[0].clone()).await;
+                    let own_id = node.read().await.get_id().clone();
+                    lookup_nodes(&node, &own_id).await.unwrap();
+                }
+                nodes.push(node);
+            }
+
+            let target = Key::from("target");
+            let mut expected: Vec<NodeInfo> = infos[..n - 1].to_vec();
+            expected.sort_by_key(|info| info.get_id().distance(&target));
+            expected.truncate(K);
+            let found = lookup_nodes(&nodes[n - 1], &target).await.unwrap();
+            assert_eq!(found, expected);
+            assert!(found.iter().any(|info| info.get_alt_host().is_some()));
+        });
+    }
+
+    #[test]
+    fn test_lookup_nodes_finds_k_closest() {
+        block_on(async {
+            let n = 30;
+            let (_network, nodes) = create_network(n).await;
+            for target in &["target1", "target2", "target3"] {
+                let target = Key::from(*target);
+                let found = lookup_nodes(&nodes[n - 1], &target).await.unwrap();
+                assert_eq!(found, expected_closest(n, n - 1, &target));
+            }
+        });
+    }
+
+    #[test]
+    fn test_lookup_trusts_id_over_given_address() {
+        block_on(async {
+            let network = InMemoryNetwork::new();
+            let node = create_node(&network, host(0));
+            let _dual = create_dual_stack_node(&network, host(1), Some(host6(1)));
+            // copy of the contact without its second address, e.g. given by a third party
+            node.write().await.update_bucket(info(1));
+
+            let found = lookup_nodes(&node, &Key::from("k1")).await.unwrap();
+            assert_eq!(found, vec![info(1)]);
+            let contacts = node.read().await.contacts();
+            assert_eq!(contacts.len(), 1);
+            assert_eq!(contacts[0].get_failures(), 0);
+
+            // the contact is updated with the addresses it signed, in the background
+            for _ in 0..100 {
+                let contacts = node.read().await.contacts();
+                if contacts[0].get_info().get_alt_host() == Some(&host6(1)) {
+                    return;
+                }
+                task::sleep(Duration::from_millis(10)).await;
+            }
+            panic!("contact not updated with its second address");
+        });
+    }
+
+    #[test]
+    fn test_put_and_get_across_network() {
+        block_on(async {
+            let (_network, nodes) = create_network(20).await;
+            let key = Key::from("k1");
+            let res = put(
+                &nodes[3],
+                key.clone(),
+                b"v1".to_vec(),
+                DEFAULT_TTL,
+                DEFAULT_MIN_REPLICAS,
+            )
+            .await
+            .unwrap();
+            assert!(res.is_success());
+
+            let found = get(&nodes[15], &key).await.unwrap().unwrap();
+            assert_eq!(found.get_value(), &b"v1".to_vec());
+        });
+    }
+
+    #[test]
+    fn test_only_successful_put_published() {
+        block_on(async {
+            // single node, which can't find a second replica
+            let network = InMemoryNetwork::new();
+            let node = create_node(&network, host(0));
+            let res = put(
+                &node,
+                "k1".into(),
+                b"v1".to_vec(),
+                REPUBLISH_ORIGINAL_INTERVAL,
+                DEFAULT_MIN_REPLICAS,
+            )
+            .await
+            .unwrap();
+            assert!(!res.is_success());
+            assert!(node
+                .write()
+                .await
+                .due_publications(REPUBLISH_ORIGINAL_INTERVAL)
+                .is_empty());
+
+            let (_network, nodes) = create_network(10).await;
+            let res = put(
+                &nodes[3],
+                "k1".into(),
+                b"v1".to_vec(),
+                REPUBLISH_ORIGINAL_INTERVAL,
+                DEFAULT_MIN_REPLICAS,
+            )
+            .await
+            .unwrap();
+            assert!(res.is_success());
+            let due = nodes[3]
+                .write()
+                .await
+                .due_publications(REPUBLISH_ORIGINAL_INTERVAL);
+            assert_eq!(due.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_get_survives_loss_of_single_node() {
+        block_on(async {
+            let (network, nodes) = create_network(20).await;
+            let key = Key::from("k1");
+            let res = put(
+                &nodes[3],
+                key.clone(),
+                b"v1".to_vec(),
+                DEFAULT_TTL,
+                DEFAULT_MIN_REPLICAS,
+            )
+            .await
+            .unwrap();
+
+            // take the closest replica offline
+            let (replica, _) = res
+                .get_replicas()
+                .iter()
+                .filter(|(info, r)| r.is_ok() && info.get_host() != &host(3))
+                .min_by_key(|(info, _)| info.get_id().distance(&key))
+                .unwrap();
+            network.unregister(replica.get_host());
+
+            // get from a node which doesn't hold the value itself
+            let getter = (0..nodes.len())
+                .find(|i| {
+                    res.get_replicas()
+                        .iter()
+                        .all(|(info, _)| info.get_host() != &host(*i))
+                })
+                .unwrap();
+            let found = get(&nodes[getter], &key).await.unwrap().unwrap();
+            assert!(found.get_hops() > 0);
+            assert_eq!(found.get_value(), &b"v1".to_vec());
+            assert_ne!(found.get_from(), replica);
+        });
+    }
+
+    #[test]
+    fn test_get_missing_value() {
+        block_on(async {
+            let (_network, nodes) = create_network(10).await;
+            assert!(get(&nodes[5], &Key::from("missing"))
+                .await
+                .unwrap()
+                .is_none());
+        });
+    }
+
+    #[test]
+    fn test_bootstrap_joins_network() {
+        block_on(async {
+            let network = InMemoryNetwork::new();
+            let mut nodes = vec![create_node(&network, host(0))];
+            for i in 1..30 {
+                let node = create_node(&network, host(i));
+                assert!(bootstrap(&node, &[host(0)]).await.unwrap() > 0);
+                nodes.push(node);
+            }
+
+            // the last node to join knows more than its single seed
+            let count = nodes[29].read().await.contact_count();
+            assert!(count >= K, "only {} contacts", count);
+        });
+    }
+
+    #[test]
+    fn test_bootstrap_skips_unreachable_seeds() {
+        block_on(async {
+            let network = InMemoryNetwork::new();
+            let _seed = create_node(&network, host(0));
+            let node = create_node(&network, host(1));
+            let count = bootstrap(&node, &[host(5), host(0), host(6)])
+                .await
+                .unwrap();
+            assert_eq!(count, 1);
+        });
+    }
+
+    #[test]
+    fn test_bootstrap_fails_without_reachable_seed() {
+        block_on(async {
+            let network = InMemoryNetwork::new();
+            let node = create_node(&network, host(0));
+            let res = bootstrap(&node, &[host(1), host(2)]).await;
+            assert!(matches!(res, Err(Error::NoSeedReachable)));
+            let res = bootstrap(&node, &[]).await;
+            assert!(matches!(res, Err(Error::NoSeedReachable)));
+        });
+    }
+
+    #[test]
+    fn test_refresh_idle_buckets() {
+        block_on(async {
+            let (_network, nodes) = create_network(20).await;
+            let node = &nodes[19];
+            assert_eq!(
+                refresh_idle_buckets(node, Duration::from_secs(3600))
+                    .await
+                    .unwrap(),
+                0
+            );
+
+            task::sleep(Duration::from_millis(10)).await;
+            let idle = node
+                .read()
+                .await
+                .idle_buckets(Duration::from_millis(5))
+                .len();
+            assert!(idle > 0);
+            let start = Instant::now();
+            let refreshed = refresh_idle_buckets(node, Duration::from_millis(5))
+                .await
+                .unwrap();
+            assert_eq!(refreshed, idle);
+            // every refreshed bucket has been looked up since
+            assert!(node.read().await.idle_buckets(start.elapsed()).is_empty());
+        });
+    }
+
+    #[test]
+    fn test_value_expires_across_network() {
+        block_on(async {
+            let (_network, nodes) = create_network(10).await;
+            let live = Key::from("live");
+            let expired = Key::from("expired");
+            for (key, ttl) in &[(&live, DEFAULT_TTL), (&expired, Duration::from_secs(0))] {
+                let res = put(
+                    &nodes[3],
+                    (*key).clone(),
+                    b"v1".to_vec(),
+                    *ttl,
+                    DEFAULT_MIN_REPLICAS,
+                )
+                .await
+                .unwrap();
+                assert!(res.is_success());
+            }
+
+            assert!(get(&nodes[5], &live).await.unwrap().is_some());
+            assert!(get(&nodes[5], &expired).await.unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn test_ttl_shrinks_with_distance_from_key() {
+        block_on(async {
+            let (_network, nodes) = create_network(30).await;
+            let key = Key::from("k1");
+            let ttl = DEFAULT_TTL;
+
+            let distance = |i: &usize| info(*i).get_id().distance(&key);
+            // the farthest node from the key knows more than k nodes closer to it
+            let farthest = &nodes[(0..nodes.len()).max_by_key(distance).unwrap()];
+            let closest = &nodes[(0..nodes.len()).min_by_key(distance).unwrap()];
+            farthest
+                .write()
+                .await
+                .store(key.clone(), b"v1".to_vec(), ttl)
+                .unwrap();
+            closest
+                .write()
+                .await
+                .store(key.clone(), b"v1".to_vec(), ttl)
+                .unwrap();
+
+            // remaining ttl, so that the test doesn't depend on how long it runs
+            let farthest_ttl = farthest.read().await.stored_keys()[0].2;
+            let closest_ttl = closest.read().await.stored_keys()[0].2;
+            assert!(farthest_ttl <= ttl / 2);
+            assert!(closest_ttl > ttl / 2);
+        });
+    }
+
+    #[test]
+    fn test_republish_to_new_closest_nodes() {
+        block_on(async {
+            let (network, mut nodes) = create_network(10).await;
+            let key = Key::from("k1");
+            put(
+                &nodes[3],
+                key.clone(),
+                b"v1".to_vec(),
+                DEFAULT_TTL,
+                DEFAULT_MIN_REPLICAS,
+            )
+            .await
+            .unwrap();
+
+            // nodes joining later may be closer to the key than the current replicas
+            for i in 10..30 {
+                let node = create_node(&network, host(i));
+                bootstrap(&node, &[host(0)]).await.unwrap();
+                nodes.push(node);
+            }
+
+            // values stored just now are not republished
+            for node in nodes.iter() {
+                assert_eq!(republish(node, Duration::from_secs(3600)).await, 0);
+            }
+
+            task::sleep(Duration::from_millis(10)).await;
+            let mut holder = None;
+            for node in nodes.iter() {
+                if node.read().await.find_value(&key).is_some() {
+                    holder = Some(node);
+                }
+            }
+            assert_eq!(
+                republish(holder.unwrap(), Duration::from_millis(5)).await,
+                1
+            );
+
+            for info in expected_closest(nodes.len(), nodes.len(), &key) {
+                let i = (info.get_host().port() - 3000) as usize;
+                let value = nodes[i].read().await.find_value(&key);
+                assert_eq!(value, Some(b"v1".to_vec()), "{:?}", info);
+            }
+        });
+    }
+
+    #[test]
+    fn test_restored_contacts_pinged_before_trusted() {
+        block_on(async {
+            let (network, nodes) = create_network(10).await;
+            let contacts = nodes[9].read().await.contacts();
+            assert!(!contacts.is_empty());
+
+            // restart the last node with an empty routing table, while one of its contacts is gone
+            let gone = contacts[0].get_info().clone();
+            network.unregister(gone.get_host());
+            let restarted = create_node(&network, host(9));
+            let seeds: Vec<SocketAddr> =
+                contacts.iter().map(|c| *c.get_info().get_host()).collect();
+            bootstrap(&restarted, &seeds).await.unwrap();
+
+            let restored = restarted.read().await.contacts();
+            assert!(restored.iter().all(|c| c.get_info() != &gone));
+            assert!(restored.len() >= contacts.len() - 1);
+        });
+    }
+}
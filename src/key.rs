@@ -1,12 +1,16 @@
 use {
-    ring::digest::{digest, SHA256},
+    ring::{
+        digest::{digest, SHA256},
+        rand::{SecureRandom, SystemRandom},
+    },
     serde::{Deserialize, Serialize},
 };
 
 /// Key struct represents Key of (Key, Value) pair and ID of nodes.
 /// id and key are represented as 160-bit identifier.
 /// distance between two keys are calcuated using XOR.
-#[derive(Eq, PartialEq, Hash, Clone, Debug, Serialize, Deserialize)]
+/// Keys are ordered as 160-bit big-endian integers, so sorting distances sorts by closeness.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Debug, Serialize, Deserialize)]
 pub struct Key([u8; 20]);
 
 impl Key {
@@ -38,6 +42,73 @@ impl Key {
         }
         b
     }
+
+    /// random key, e.g. to be used as id of a new node
+    pub fn random() -> Self {
+        let mut arr = [0u8; 20];
+        SystemRandom::new()
+            .fill(&mut arr)
+            .expect("failed to generate random key");
+        Self(arr)
+    }
+
+    /// random key sharing its first `prefix_len` bits with this key
+    pub fn random_with_prefix(&self, prefix_len: u32) -> Self {
+        if prefix_len >= 160 {
+            return self.clone();
+        }
+        let mut distance = [0u8; 20];
+        SystemRandom::new()
+            .fill(&mut distance)
+            .expect("failed to generate random key");
+
+        let byte = (prefix_len / 8) as usize;
+        for b in distance[..byte].iter_mut() {
+            *b = 0;
+        }
+        distance[byte] &= 0xff >> (prefix_len % 8);
+
+        self.distance(&Self(distance))
+    }
+
+    /// whether bit i is set, counting from the most significant bit
+    /// panics if i is 160 or more
+    pub fn bit(&self, i: u32) -> bool {
+        self.0[(i / 8) as usize] & (0x80 >> (i % 8)) != 0
+    }
+
+    /// this key with bit i flipped, counting from the most significant bit
+    /// panics if i is 160 or more
+    pub fn flip_bit(&self, i: u32) -> Self {
+        let mut arr = self.0;
+        arr[(i / 8) as usize] ^= 0x80 >> (i % 8);
+        Self(arr)
+    }
+
+    /// lowercase hex representation of 40 characters
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// parse key from hex representation given by `to_hex`
+    pub fn from_hex(s: &str) -> Option<Self> {
+        if s.len() != 40 || !s.is_ascii() {
+            return None;
+        }
+        let mut arr = [0; 20];
+        for (i, b) in arr.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some(Self(arr))
+    }
+
+    /// id of the node holding given Ed25519 public key
+    pub fn from_public_key(public_key: &[u8]) -> Self {
+        let hashed = digest(&SHA256, public_key);
+        let mut arr = [0; 20];
+        arr.copy_from_slice(&hashed.as_ref()[0..20]);
+        Self(arr)
+    }
 }
 
 impl From<String> for Key {
@@ -80,4 +151,37 @@ mod tests {
         assert_eq!(key3.most_significant_bit(), 6);
         assert_eq!(key4.most_significant_bit(), 0);
     }
+
+    #[test]
+    fn test_hex() {
+        let key = Key::new([
+            0, 1, 2, 0xab, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+        ]);
+        let hex = key.to_hex();
+        assert_eq!(hex, "000102abff000000000000000000000000000010");
+        assert_eq!(Key::from_hex(&hex), Some(key));
+        assert_eq!(Key::from_hex("0001"), None);
+        assert_eq!(Key::from_hex(&hex.replace("ab", "zz")), None);
+    }
+
+    #[test]
+    fn test_random_with_prefix() {
+        let key = Key::from("key");
+        for prefix_len in &[0, 1, 7, 8, 9, 80, 159] {
+            let random = key.random_with_prefix(*prefix_len);
+            assert!(key.distance(&random).most_significant_bit() >= *prefix_len);
+        }
+        assert_eq!(key.random_with_prefix(160), key);
+    }
+
+    #[test]
+    fn test_bits() {
+        let mut arr = [0; 20];
+        arr[1] = 0b0100_0000;
+        let key = Key::new(arr);
+        assert!(key.bit(9));
+        assert!(!key.bit(8) && !key.bit(10));
+        assert_eq!(key.flip_bit(9), Key::new([0; 20]));
+        assert!(key.flip_bit(159).bit(159));
+    }
 }
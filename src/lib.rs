@@ -1,10 +1,28 @@
 #![feature(try_trait)]
 
+pub mod admin;
+pub mod bootstrap;
 pub mod bucket;
+pub mod client;
+pub mod config;
+pub mod contacts;
+pub mod disk_storage;
 pub mod error;
+pub mod expiration;
+pub mod frame;
+pub mod gateway;
+pub mod identity;
 pub mod in_memory_hash_table;
+pub mod in_memory_transport;
 pub mod key;
+pub mod lookup;
 pub mod node;
+pub mod refresh;
+pub mod replication;
 pub mod request;
 pub mod response;
 pub mod rpc;
+pub mod server;
+pub mod storage;
+pub mod transport;
+pub mod udp;
@@ -0,0 +1,359 @@
+use {
+    crate::{
+        error::Result,
+        key::Key,
+        node::{update_contact, Node, NodeInfo},
+        request::Request,
+        response::ResponseBody,
+        rpc::Rpc,
+    },
+    async_std::{sync::RwLock, task},
+    std::sync::Arc,
+};
+
+/// default number of FIND_NODE requests sent concurrently in a single lookup round, set per
+/// node with `Config`
+pub const DEFAULT_ALPHA: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    NotQueried,
+    Pending,
+    Responded,
+    Failed,
+}
+
+/// nodes discovered during a lookup, kept sorted by distance to the target.
+struct Shortlist {
+    target: Key,
+    own_id: Key,
+    k: usize,
+    entries: Vec<(NodeInfo, State)>,
+}
+
+impl Shortlist {
+    fn new(target: Key, own_id: Key, k: usize) -> Self {
+        Self {
+            target,
+            own_id,
+            k,
+            entries: Vec::new(),
+        }
+    }
+
+    /// merge given nodes into the shortlist, ignoring ourselves and nodes already known.
+    fn insert(&mut self, nodes: Vec<NodeInfo>) {
+        for node in nodes {
+            if node.get_id() == &self.own_id
+                || self
+                    .entries
+                    .iter()
+                    .any(|(n, _)| n.get_id() == node.get_id())
+            {
+                continue;
+            }
+            self.entries.push((node, State::NotQueried));
+        }
+        let target = &self.target;
+        self.entries
+            .sort_by_key(|(n, _)| n.get_id().distance(target));
+    }
+
+    /// distance of the closest node which has not failed to respond
+    fn closest_distance(&self) -> Option<Key> {
+        self.entries
+            .iter()
+            .find(|(_, s)| *s != State::Failed)
+            .map(|(n, _)| n.get_id().distance(&self.target))
+    }
+
+    /// pick at most n nodes not yet queried among k closest alive nodes and mark them pending.
+    fn take_unqueried(&mut self, n: usize) -> Vec<NodeInfo> {
+        self.entries
+            .iter_mut()
+            .filter(|(_, s)| *s != State::Failed)
+            .take(self.k)
+            .filter(|(_, s)| *s == State::NotQueried)
+            .take(n)
+            .map(|(node, s)| {
+                *s = State::Pending;
+                node.clone()
+            })
+            .collect()
+    }
+
+    fn set_state(&mut self, id: &Key, state: State) {
+        if let Some((_, s)) = self.entries.iter_mut().find(|(n, _)| n.get_id() == id) {
+            *s = state;
+        }
+    }
+
+    /// at most n closest nodes which responded to us
+    fn responded(&self, n: usize) -> Vec<NodeInfo> {
+        self.entries
+            .iter()
+            .filter(|(_, s)| *s == State::Responded)
+            .take(n)
+            .map(|(node, _)| node.clone())
+            .collect()
+    }
+}
+
+/// value found by `get` together with the node which served it
+#[derive(Debug)]
+pub struct GetResult {
+    value: Vec<u8>,
+    from: NodeInfo,
+    hops: usize,
+}
+
+impl GetResult {
+    pub fn get_value(&self) -> &Vec<u8> {
+        &self.value
+    }
+
+    pub fn get_from(&self) -> &NodeInfo {
+        &self.from
+    }
+
+    /// number of lookup rounds taken until the value was found, 0 when held locally
+    pub fn get_hops(&self) -> usize {
+        self.hops
+    }
+}
+
+enum Found {
+    Nodes(Vec<NodeInfo>),
+    Value(GetResult),
+}
+
+/// iterative FIND_NODE lookup described in the Kademlia paper.
+/// starting from the k closest nodes in our buckets, send FIND_NODE to alpha closest nodes not
+/// queried yet, merge returned nodes into the shortlist and repeat.
+/// when a round does not find any node closer than already known, query every k closest node
+/// not queried yet, and finish once all of them are queried.
+/// returns at most k closest nodes which responded.
+pub async fn lookup_nodes(node: &Arc<RwLock<Node>>, target: &Key) -> Result<Vec<NodeInfo>> {
+    match iterative_find(node, Rpc::FindNode(target.clone()), target).await? {
+        Found::Nodes(nodes) => Ok(nodes),
+        Found::Value(_) => unreachable!("FIND_NODE never returns a value"),
+    }
+}
+
+/// iterative FIND_VALUE lookup.
+/// same procedure as `lookup_nodes`, but stops as soon as any node returns the value.
+/// returns None if the value is neither held locally nor found before the shortlist is exhausted.
+pub async fn get(node: &Arc<RwLock<Node>>, key: &Key) -> Result<Option<GetResult>> {
+    {
+        let node = node.read().await;
+        if let Some(value) = node.find_value(key) {
+            return Ok(Some(GetResult {
+                value,
+                from: node.get_info(),
+                hops: 0,
+            }));
+        }
+    }
+
+    match iterative_find(node, Rpc::FindValue(key.clone()), key).await? {
+        Found::Nodes(_) => Ok(None),
+        Found::Value(res) => Ok(Some(res)),
+    }
+}
+
+async fn iterative_find(node: &Arc<RwLock<Node>>, rpc: Rpc, target: &Key) -> Result<Found> {
+    let (own_info, identity, config, initial, transport) = {
+        let mut node = node.write().await;
+        node.touch_bucket(target);
+        (
+            node.get_info(),
+            node.get_identity(),
+            node.get_config().clone(),
+            node.find_node(target),
+            node.get_transport(),
+        )
+    };
+    let (k, alpha, timeout) = (config.get_k(), config.get_alpha(), config.get_rpc_timeout());
+    let mut shortlist = Shortlist::new(target.clone(), own_info.get_id().clone(), k);
+    shortlist.insert(initial);
+
+    let mut parallelism = alpha;
+    let mut hops = 0;
+    loop {
+        let closest_before = shortlist.closest_distance();
+        let batch = shortlist.take_unqueried(parallelism);
+        if batch.is_empty() {
+            break;
+        }
+        hops += 1;
+
+        let handles: Vec<_> = batch
+            .into_iter()
+            .map(|to| {
+                let host = *to.reachable_host(&own_info);
+                let mut req = Request::new(Some(own_info.clone()), rpc.clone(), host);
+                req.sign(&identity);
+                let transport = transport.clone();
+                task::spawn(async move { (to, transport.send(&req, timeout).await) })
+            })
+            .collect();
+
+        let mut found = None;
+        for handle in handles {
+            let (to, res) = handle.await;
+            // a node answering with another id is not the one we asked. its addresses may differ
+            // from the ones we were given, the signed ones it sent are kept.
+            let res = res
+                .as_ref()
+                .ok()
+                .filter(|r| r.get_from().get_id() == to.get_id())
+                .and_then(|r| r.get_body().map(|body| (r.get_from().clone(), body)));
+            match res {
+                Some((from, ResponseBody::NODES(nodes))) => {
+                    shortlist.set_state(to.get_id(), State::Responded);
+                    shortlist.insert(nodes.clone());
+                    spawn_update_contact(node, from);
+                }
+                Some((from, ResponseBody::VALUE(value))) => {
+                    shortlist.set_state(to.get_id(), State::Responded);
+                    if found.is_none() {
+                        found = Some(GetResult {
+                            value: value.clone(),
+                            from: from.clone(),
+                            hops,
+                        });
+                    }
+                    spawn_update_contact(node, from);
+                }
+                _ => {
+                    shortlist.set_state(to.get_id(), State::Failed);
+                    node.write().await.mark_stale(&to);
+                }
+            }
+        }
+        if let Some(res) = found {
+            return Ok(Found::Value(res));
+        }
+
+        let improved = match (closest_before, shortlist.closest_distance()) {
+            (None, Some(_)) => true,
+            (Some(before), Some(after)) => after < before,
+            _ => false,
+        };
+        parallelism = if improved { alpha } else { k };
+    }
+
+    Ok(Found::Nodes(shortlist.responded(k)))
+}
+
+/// update bucket in background so that a ping to a full bucket doesn't delay the lookup
+fn spawn_update_contact(node: &Arc<RwLock<Node>>, node_info: NodeInfo) {
+    let node = node.clone();
+    task::spawn(async move { update_contact(&node, node_info).await });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const K: usize = 10;
+    const ALPHA: usize = DEFAULT_ALPHA;
+
+    fn create_node_info(port: u16, id: u8) -> NodeInfo {
+        let mut key = [0; 20];
+        key[0] = id;
+        NodeInfo::new(
+            format!("127.0.0.1:{}", port).parse().unwrap(),
+            Key::new(key),
+        )
+    }
+
+    #[test]
+    fn test_shortlist_insert_sorted_without_duplicates() {
+        let mut shortlist = Shortlist::new(Key::new([0; 20]), Key::new([255; 20]), K);
+        shortlist.insert(vec![create_node_info(2003, 3), create_node_info(2001, 1)]);
+        shortlist.insert(vec![create_node_info(2002, 2), create_node_info(2001, 1)]);
+
+        let nodes: Vec<NodeInfo> = shortlist.entries.iter().map(|(n, _)| n.clone()).collect();
+        assert_eq!(
+            nodes,
+            vec![
+                create_node_info(2001, 1),
+                create_node_info(2002, 2),
+                create_node_info(2003, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shortlist_ignores_own_id() {
+        let own = create_node_info(2000, 1);
+        let mut shortlist = Shortlist::new(Key::new([0; 20]), own.get_id().clone(), K);
+        shortlist.insert(vec![own, create_node_info(2002, 2)]);
+        assert_eq!(shortlist.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_shortlist_take_unqueried() {
+        let mut shortlist = Shortlist::new(Key::new([0; 20]), Key::new([255; 20]), K);
+        shortlist.insert(
+            (1..=5)
+                .map(|i| create_node_info(2000 + i as u16, i))
+                .collect(),
+        );
+
+        let first = shortlist.take_unqueried(ALPHA);
+        assert_eq!(first.len(), ALPHA);
+        assert_eq!(first[0], create_node_info(2001, 1));
+
+        let second = shortlist.take_unqueried(ALPHA);
+        assert_eq!(second.len(), 2);
+        assert!(shortlist.take_unqueried(ALPHA).is_empty());
+    }
+
+    #[test]
+    fn test_shortlist_only_queries_k_closest() {
+        let mut shortlist = Shortlist::new(Key::new([0; 20]), Key::new([255; 20]), K);
+        shortlist.insert(
+            (1..=K as u8 + 2)
+                .map(|i| create_node_info(2000 + i as u16, i))
+                .collect(),
+        );
+
+        assert_eq!(shortlist.take_unqueried(K + 2).len(), K);
+
+        // a failed node frees a slot in the k closest
+        shortlist.set_state(create_node_info(2001, 1).get_id(), State::Failed);
+        assert_eq!(shortlist.take_unqueried(K + 2).len(), 1);
+    }
+
+    #[test]
+    fn test_shortlist_closest_distance_skips_failed() {
+        let target = Key::new([0; 20]);
+        let mut shortlist = Shortlist::new(target.clone(), Key::new([255; 20]), K);
+        assert_eq!(shortlist.closest_distance(), None);
+
+        let node1 = create_node_info(2001, 1);
+        let node2 = create_node_info(2002, 2);
+        shortlist.insert(vec![node1.clone(), node2.clone()]);
+        shortlist.set_state(node1.get_id(), State::Failed);
+        assert_eq!(
+            shortlist.closest_distance(),
+            Some(node2.get_id().distance(&target))
+        );
+    }
+
+    #[test]
+    fn test_shortlist_responded() {
+        let mut shortlist = Shortlist::new(Key::new([0; 20]), Key::new([255; 20]), K);
+        let node1 = create_node_info(2001, 1);
+        let node2 = create_node_info(2002, 2);
+        let node3 = create_node_info(2003, 3);
+        shortlist.insert(vec![node1.clone(), node2.clone(), node3.clone()]);
+        shortlist.set_state(node1.get_id(), State::Failed);
+        shortlist.set_state(node2.get_id(), State::Responded);
+        shortlist.set_state(node3.get_id(), State::Responded);
+
+        assert_eq!(shortlist.responded(K), vec![node2, node3]);
+    }
+}
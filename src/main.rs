@@ -1,90 +1,184 @@
 #![feature(try_trait)]
 
+mod admin;
+mod bootstrap;
 mod bucket;
+mod client;
+mod config;
+mod contacts;
+mod disk_storage;
 mod error;
+mod expiration;
+mod frame;
+mod gateway;
+mod identity;
 mod in_memory_hash_table;
 mod key;
+mod lookup;
 mod node;
+mod refresh;
+mod replication;
 mod request;
 mod response;
 mod rpc;
+mod server;
+mod storage;
+mod transport;
+mod udp;
 
 use {
+    admin::serve_admin,
     async_std::{
-        io::BufReader,
-        net::{TcpListener, TcpStream},
-        prelude::*,
+        net::{TcpListener, UdpSocket},
         sync::RwLock,
         task,
     },
+    bootstrap::{bootstrap, read_seeds},
     clap::{App, Arg},
-    error::Result,
+    config::Config,
+    contacts::{load_contacts, snapshot_loop, CONTACTS_FILE},
+    disk_storage::DiskStorage,
+    error::{Error, Result},
+    expiration::expire_loop,
+    futures::future,
+    gateway::{max_value_size, serve_http},
+    identity::{load_or_create_identity, Identity, IDENTITY_FILE},
     node::Node,
-    request::Request,
-    rpc::Rpc,
-    std::{net::SocketAddrV4, sync::Arc},
+    refresh::refresh_loop,
+    replication::republish_loop,
+    std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration},
+    transport::{Protocol, TcpTransport, Transport, UdpTransport},
 };
 
-async fn start(host: SocketAddrV4, neighbor: Option<SocketAddrV4>) -> Result<()> {
-    let node = Arc::new(RwLock::new(Node::new(host)?));
+/// address and token of the admin channel
+struct AdminOptions {
+    host: SocketAddr,
+    token: Option<String>,
+}
+
+/// services for operators and local clients, served besides the RPCs when enabled
+struct LocalServices {
+    admin: Option<AdminOptions>,
+    http_host: Option<SocketAddr>,
+}
 
-    // Ping neighbor
-    if let Some(n) = neighbor {
-        let req = Request::new(Some(host.into()), Rpc::Ping, n.into());
-        let res = req.send().await;
-        println!("{:?}", res);
-        let mut node = node.write().await;
-        node.update_bucket(n.into());
+async fn start(
+    host: SocketAddr,
+    alt_host: Option<SocketAddr>,
+    mut seeds: Vec<SocketAddr>,
+    protocol: Protocol,
+    config: Config,
+    data_dir: Option<PathBuf>,
+    services: LocalServices,
+) -> Result<()> {
+    let identity = match &data_dir {
+        Some(dir) => load_or_create_identity(dir.join(IDENTITY_FILE))?,
+        None => Identity::generate()?.0,
+    };
+    let max_frame_size = config.get_max_frame_size();
+    let mut node = Node::with_config(host, identity, config.clone())?;
+    if let Some(alt_host) = alt_host {
+        node.set_alt_host(alt_host);
+    }
+    let own_info = node.get_info();
+    let mut restored = 0;
+    let contacts_path = data_dir.as_ref().map(|dir| dir.join(CONTACTS_FILE));
+    if let Some(dir) = &data_dir {
+        node.set_storage(Box::new(DiskStorage::open(dir)?));
+    }
+    match alt_host {
+        Some(alt_host) => println!(
+            "Node {} listening on {} and {}",
+            node.get_id().to_hex(),
+            host,
+            alt_host
+        ),
+        None => println!("Node {} listening on {}", node.get_id().to_hex(), host),
+    }
+    // contacts saved before the restart are only trusted once they respond to the bootstrap
+    if let Some(path) = &contacts_path {
+        for contact in load_contacts(path)? {
+            let host = *contact.get_info().reachable_host(&own_info);
+            if !seeds.contains(&host) {
+                seeds.push(host);
+                restored += 1;
+            }
+        }
     }
+    let node = Arc::new(RwLock::new(node));
+    let transport: Arc<dyn Transport> = match protocol {
+        Protocol::Tcp => Arc::new(TcpTransport::new(max_frame_size)),
+        Protocol::Udp => Arc::new(UdpTransport::new(
+            config.get_udp_retries(),
+            TcpTransport::new(max_frame_size),
+        )),
+    };
+    node.write().await.set_transport(transport);
+
+    // a dual-stack node serves every protocol on both addresses
+    let hosts: Vec<SocketAddr> = std::iter::once(host).chain(alt_host).collect();
 
-    let listener = TcpListener::bind(host).await?;
-    let mut incoming = listener.incoming();
-    while let Some(Ok(stream)) = incoming.next().await {
+    // both protocols are always served, so that nodes sending over either of them can reach
+    // each other. the protocol given only chooses how this node sends its requests.
+    for host in hosts.iter() {
+        let socket = UdpSocket::bind(host).await?;
         let node = node.clone();
-        task::spawn(async { connection_loop(stream, node).await });
+        task::spawn(async move { udp::serve(socket, node).await });
     }
-    Ok(())
-}
 
-async fn connection_loop(stream: TcpStream, node: Arc<RwLock<Node>>) -> Result<()> {
-    println!("Incoming stream from '{:?}'", stream.peer_addr()?);
-    let stream = Arc::new(stream);
-    let reader = BufReader::new(&*stream);
-    let mut lines = reader.lines();
-    while let Some(Ok(line)) = lines.next().await {
-        let deserialized = serde_json::from_str::<Request>(&line);
-        if deserialized.is_err() {
-            println!("Request deserialize fail: {:?}", deserialized.err());
-            continue;
+    // serve before joining, so that seeds can reach us back during the bootstrap
+    let mut servers = Vec::new();
+    for host in hosts.iter() {
+        let listener = TcpListener::bind(host).await?;
+        servers.push(task::spawn(server::serve(
+            listener,
+            node.clone(),
+            max_frame_size,
+        )));
+    }
+
+    if let Some(admin) = services.admin {
+        let listener = TcpListener::bind(admin.host).await?;
+        if admin.token.is_none() && !admin.host.ip().is_loopback() {
+            println!("No admin token given, only loopback clients will be served");
         }
-        let req = deserialized.unwrap();
-        println!("{:?}", req);
-        match req.get_rpc() {
-            Rpc::Ping => {
-                let mut stream = &*stream;
-                stream.write("PONG".as_bytes()).await?;
-                stream.write(b"\n").await?;
-            }
-            Rpc::FindValue(k) => {
-                let node = node.read().await;
-                if let Some(v) = node.find_value(&k) {
-                    let mut stream = &*stream;
-                    stream.write_all(v.as_ref()).await?;
-                    stream.write(b"\n").await?;
-                }
-            }
-            Rpc::FindNode(_k) => unimplemented!("unimplemented FIND_NODE"),
-            Rpc::Store(k, v) => {
-                let mut node = node.write().await;
-                let _ = node.store(k.clone(), v.clone());
+        println!("Admin channel listening on {}", admin.host);
+        task::spawn(serve_admin(listener, node.clone(), admin.token));
+    }
+
+    if let Some(http_host) = services.http_host {
+        let listener = TcpListener::bind(http_host).await?;
+        println!("HTTP gateway listening on {}", http_host);
+        task::spawn(serve_http(
+            listener,
+            node.clone(),
+            max_value_size(max_frame_size),
+        ));
+    }
+
+    // the first node of a network has no seed to join through
+    if !seeds.is_empty() {
+        match bootstrap(&node, &seeds).await {
+            Ok(count) => println!("Joined the network with {} contacts", count),
+            // restored contacts may all be gone, leaving us as the first node of a new network
+            Err(Error::NoSeedReachable) if seeds.len() == restored => {
+                println!("None of {} restored contacts responded", restored)
             }
+            Err(e) => return Err(e),
         }
-        let mut node = node.write().await;
-        match req.get_from() {
-            Some(n) => node.update_bucket(n.clone()),
-            _ => {}
-        }
     }
+
+    task::spawn(expire_loop(node.clone(), config.get_expire_interval()));
+    task::spawn(refresh_loop(node.clone(), config.get_refresh_interval()));
+    task::spawn(republish_loop(
+        node.clone(),
+        config.get_republish_interval(),
+    ));
+    if let Some(path) = contacts_path {
+        task::spawn(snapshot_loop(node, path, config.get_snapshot_interval()));
+    }
+
+    future::try_join_all(servers).await?;
     Ok(())
 }
 
@@ -94,25 +188,148 @@ async fn main() {
         .version("0.1.0")
         .about("server app for kadrs")
         .arg(Arg::with_name("host").required(true))
-        .arg(Arg::with_name("neighbor"));
+        .arg(
+            Arg::with_name("alt-host")
+                .long("alt-host")
+                .takes_value(true)
+                .help("address of the other IP family to listen on too, for dual-stack nodes"),
+        )
+        .arg(
+            Arg::with_name("neighbor")
+                .multiple(true)
+                .help("seed nodes to join the network through"),
+        )
+        .arg(
+            Arg::with_name("seeds-file")
+                .long("seeds-file")
+                .takes_value(true)
+                .help("file listing seed nodes, one address per line"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .help(
+                "TOML (.toml) or JSON file setting k, alpha, timeouts and intervals of the node",
+            ),
+        )
+        .arg(
+            Arg::with_name("max-frame-size")
+                .long("max-frame-size")
+                .takes_value(true)
+                .help("maximum size of a single request in bytes"),
+        )
+        .arg(
+            Arg::with_name("transport")
+                .long("transport")
+                .takes_value(true)
+                .possible_values(&["tcp", "udp"])
+                .default_value("tcp")
+                .help(
+                    "protocol used to send RPCs, udp falls back to tcp for large payloads. \
+                     both are always served",
+                ),
+        )
+        .arg(
+            Arg::with_name("refresh-interval")
+                .long("refresh-interval")
+                .takes_value(true)
+                .help("seconds a bucket can stay without lookup before it is refreshed"),
+        )
+        .arg(
+            Arg::with_name("admin-host")
+                .long("admin-host")
+                .takes_value(true)
+                .help("address to serve admin requests on, disabled if not given"),
+        )
+        .arg(
+            Arg::with_name("admin-token")
+                .long("admin-token")
+                .takes_value(true)
+                .requires("admin-host")
+                .help("token admin clients outside of the loopback interface must send"),
+        )
+        .arg(
+            Arg::with_name("http-host")
+                .long("http-host")
+                .takes_value(true)
+                .help("address to serve the HTTP gateway on, which isn't authenticated"),
+        )
+        .arg(
+            Arg::with_name("data-dir")
+                .long("data-dir")
+                .takes_value(true)
+                .help("directory to keep stored values and contacts in across restarts"),
+        );
     let matches = app.get_matches();
-    let host: SocketAddrV4 = match matches.value_of("host").unwrap().parse() {
+    let host: SocketAddr = match matches.value_of("host").unwrap().parse() {
         Ok(s) => s,
         Err(_) => panic!("Invalid host string"),
     };
-    let neighbor: Option<SocketAddrV4> = matches
-        .value_of("neighbor")
-        .map(|s| s.parse().expect("Invalid host string"));
+    let alt_host: Option<SocketAddr> = matches
+        .value_of("alt-host")
+        .map(|s| s.parse().expect("Invalid alt host string"));
+    if let Some(alt_host) = alt_host {
+        assert!(
+            alt_host.is_ipv4() != host.is_ipv4(),
+            "Alt host must be of the other IP family than host"
+        );
+    }
+    let mut seeds: Vec<SocketAddr> = matches.values_of("neighbor").map_or(Vec::new(), |values| {
+        values
+            .map(|s| s.parse().expect("Invalid host string"))
+            .collect()
+    });
+    if let Some(path) = matches.value_of("seeds-file") {
+        seeds.extend(read_seeds(path).expect("Invalid seeds file"));
+    }
+    let config = match matches.value_of("config") {
+        Some(path) => Config::load(path).expect("Invalid config file"),
+        None => Config::default(),
+    };
+    // options given on the command line take precedence over the config file
+    let mut builder = config.to_builder();
+    if let Some(s) = matches.value_of("max-frame-size") {
+        builder = builder.max_frame_size(s.parse().expect("Invalid max frame size"));
+    }
+    if let Some(s) = matches.value_of("refresh-interval") {
+        builder = builder.refresh_interval(Duration::from_secs(
+            s.parse().expect("Invalid refresh interval"),
+        ));
+    }
+    let config = builder.build().expect("Invalid config");
+    let protocol: Protocol = matches
+        .value_of("transport")
+        .unwrap()
+        .parse()
+        .expect("Invalid transport");
+
+    let data_dir = matches.value_of("data-dir").map(PathBuf::from);
+    let services = LocalServices {
+        admin: matches.value_of("admin-host").map(|s| AdminOptions {
+            host: s.parse().expect("Invalid admin host string"),
+            token: matches.value_of("admin-token").map(str::to_owned),
+        }),
+        http_host: matches
+            .value_of("http-host")
+            .map(|s| s.parse().expect("Invalid HTTP host string")),
+    };
 
     // start a server
-    let server = start(host, neighbor).await;
+    let server = start(host, alt_host, seeds, protocol, config, data_dir, services).await;
     match server {
         Ok(..) => println!("Server exited"),
-        Err(e) => println!("Server exited with unexpected error: {}", e),
+        Err(e) => {
+            println!("Server exited with unexpected error: {}", e);
+            std::process::exit(1);
+        }
     }
     //
-    // request sample input
-    // {"node_info":{"host":"127.0.0.1:2000","id":[44,112,225,43,122,6,70,249,34,121,244,39,199,179,142,115,52,216,229,56]},"rpc":{"FindValue":[129,116,9,150,135,162,102,33,244,226,205,215,204,3,179,218,206,219,63,185]}}
-    // {"node_info":{"host":"127.0.0.1:2000","id":[44,112,225,43,122,6,70,249,34,121,244,39,199,179,142,115,52,216,229,56]},"rpc":{"Store":[[129,116,9,150,135,162,102,33,244,226,205,215,204,3,179,218,206,219,63,185],[104,101,108,108,111,32,119,111,114,108,100]]}}
+    // request wire format
+    // over TCP, every request and response is a frame: a 4-byte big-endian length followed by
+    // the JSON of the message. over UDP, a datagram holds the JSON alone.
+    // a request from a node carries its node info and is signed with the node's key, one whose
+    // signature doesn't match is answered with an ERROR body. requests without node info come
+    // from clients and are unsigned. responses are always signed by the answering node.
     //
 }
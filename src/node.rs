@@ -1,64 +1,371 @@
 use {
-    crate::{bucket::KBucket, error::Result, in_memory_hash_table::Table, key::Key},
+    crate::{
+        admin::Counters,
+        bucket::{Contact, KBucket, RoutingSnapshot},
+        config::Config,
+        error::Result,
+        identity::Identity,
+        in_memory_hash_table::Table,
+        key::Key,
+        replication::REPUBLISH_ORIGINAL_INTERVAL,
+        request::Request,
+        response::ResponseBody,
+        rpc::Rpc,
+        storage::Storage,
+        transport::{TcpTransport, Transport},
+    },
+    async_std::sync::RwLock,
     serde::{Deserialize, Serialize},
-    std::net::SocketAddrV4,
+    std::{
+        collections::HashMap,
+        net::SocketAddr,
+        sync::Arc,
+        time::{Duration, Instant, SystemTime},
+    },
 };
 
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct NodeInfo {
-    host: SocketAddrV4,
+    host: SocketAddr,
     id: Key,
+    /// address in the other family of a dual-stack node
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    alt_host: Option<SocketAddr>,
 }
 
 impl NodeInfo {
-    pub fn new(host: SocketAddrV4, id: Key) -> Self {
-        Self { host, id }
+    pub fn new(host: SocketAddr, id: Key) -> Self {
+        Self::with_alt_host(host, None, id)
+    }
+
+    /// info of a node also reachable at an address of the other family
+    pub fn with_alt_host(host: SocketAddr, alt_host: Option<SocketAddr>, id: Key) -> Self {
+        Self { host, id, alt_host }
     }
 
     pub fn get_id(&self) -> &Key {
         &self.id
     }
-    pub fn get_host(&self) -> &SocketAddrV4 {
+    pub fn get_host(&self) -> &SocketAddr {
         &self.host
     }
-}
 
-impl From<SocketAddrV4> for NodeInfo {
-    fn from(host: SocketAddrV4) -> NodeInfo {
-        let id = Key::from(format!("{}", host));
-        NodeInfo { host, id }
+    pub fn get_alt_host(&self) -> Option<&SocketAddr> {
+        self.alt_host.as_ref()
+    }
+
+    /// whether the node is reachable at given address
+    pub fn has_host(&self, host: &SocketAddr) -> bool {
+        &self.host == host || self.alt_host.as_ref() == Some(host)
+    }
+
+    /// address to reach this node at from given local node: the first of its addresses in a
+    /// family the local node has an address in, or its main address if they share no family.
+    pub fn reachable_host(&self, local: &NodeInfo) -> &SocketAddr {
+        let reachable = |host: &SocketAddr| {
+            std::iter::once(&local.host)
+                .chain(local.alt_host.as_ref())
+                .any(|l| l.is_ipv4() == host.is_ipv4())
+        };
+        std::iter::once(&self.host)
+            .chain(self.alt_host.as_ref())
+            .find(|host| reachable(host))
+            .unwrap_or(&self.host)
     }
 }
 
+/// value this node published, kept to be published again once its replicas are about to expire
+struct Publication {
+    value: Vec<u8>,
+    ttl: Duration,
+    published_at: SystemTime,
+}
+
 pub struct Node {
-    id: Key,
-    host: SocketAddrV4,
-    local_table: Table,
+    identity: Arc<Identity>,
+    host: SocketAddr,
+    alt_host: Option<SocketAddr>,
+    config: Config,
+    storage: Box<dyn Storage>,
+    publications: HashMap<Key, Publication>,
     k_bucket: KBucket,
+    transport: Arc<dyn Transport>,
+    started_at: Instant,
+    counters: Counters,
 }
 
 impl Node {
-    pub fn new(host: SocketAddrV4) -> Result<Self> {
-        let id = Key::from(format!("{}", host));
+    /// node with a new random keypair
+    pub fn new(host: SocketAddr) -> Result<Self> {
+        let (identity, _) = Identity::generate()?;
+        Self::with_identity(host, identity)
+    }
+
+    /// node with given keypair, e.g. one restored from an identity file
+    pub fn with_identity(host: SocketAddr, identity: Identity) -> Result<Self> {
+        Self::with_config(host, identity, Config::default())
+    }
 
+    /// node with given keypair and parameters
+    pub fn with_config(host: SocketAddr, identity: Identity, config: Config) -> Result<Self> {
+        let k_bucket = KBucket::new(
+            identity.get_id().clone(),
+            config.get_k(),
+            config.get_relaxed_splitting(),
+        );
         Ok(Self {
             host,
-            id,
-            local_table: Table::new(),
-            k_bucket: KBucket::new(),
+            alt_host: None,
+            identity: Arc::new(identity),
+            storage: Box::new(Table::new()),
+            publications: HashMap::new(),
+            k_bucket,
+            started_at: Instant::now(),
+            counters: Counters::default(),
+            transport: Arc::new(TcpTransport::new(config.get_max_frame_size())),
+            config,
         })
     }
 
+    pub fn get_id(&self) -> &Key {
+        self.identity.get_id()
+    }
+
+    pub fn get_info(&self) -> NodeInfo {
+        NodeInfo::with_alt_host(self.host, self.alt_host, self.get_id().clone())
+    }
+
+    /// listen on an address of the other family too, advertising it to other nodes
+    pub fn set_alt_host(&mut self, alt_host: SocketAddr) {
+        self.alt_host = Some(alt_host);
+    }
+
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
+    /// keypair used to sign requests and responses of this node
+    pub fn get_identity(&self) -> Arc<Identity> {
+        self.identity.clone()
+    }
+
+    /// transport used to send requests to other nodes
+    pub fn get_transport(&self) -> Arc<dyn Transport> {
+        self.transport.clone()
+    }
+
+    pub fn set_transport(&mut self, transport: Arc<dyn Transport>) {
+        self.transport = transport;
+    }
+
+    /// replace the storage of values, dropping values stored in the previous one
+    pub fn set_storage(&mut self, storage: Box<dyn Storage>) {
+        self.storage = storage;
+    }
+
     pub fn find_value(&self, key: &Key) -> Option<Vec<u8>> {
-        self.local_table.get(key).and_then(|v| Some(v.clone()))
+        self.storage.get(key).and_then(|v| Some(v.clone()))
+    }
+
+    /// store value for given key.
+    /// ttl is halved for every k nodes we know closer to the key than us, so that values cached
+    /// far from the key expire sooner.
+    pub fn store(&mut self, key: Key, value: Vec<u8>, ttl: Duration) -> Result<()> {
+        let closer = self
+            .k_bucket
+            .count_closer(&key, &self.get_id().distance(&key));
+        let ttl = 2u32
+            .checked_pow((closer / self.config.get_k()) as u32)
+            .map_or(Duration::from_secs(0), |d| ttl / d);
+        self.storage.put(key, value, ttl)?;
+        Ok(())
+    }
+
+    /// key, value and remaining ttl of values stored more than given duration ago.
+    /// values received within that duration were just republished by another node.
+    pub fn stored_before(&self, age: Duration) -> Vec<(Key, Vec<u8>, Duration)> {
+        self.storage.stored_before(age)
+    }
+
+    /// key, size and remaining ttl of every value stored by this node
+    pub fn stored_keys(&self) -> Vec<(Key, usize, Duration)> {
+        self.storage.entries()
+    }
+
+    /// time elapsed since this node was created
+    pub fn get_uptime(&self) -> Duration {
+        self.started_at.elapsed()
     }
 
-    pub fn store(&mut self, key: Key, value: Vec<u8>) {
-        self.local_table.put(key, value);
+    /// number of requests handled by this node
+    pub fn get_counters(&self) -> &Counters {
+        &self.counters
     }
 
-    pub fn update_bucket(&mut self, node_info: NodeInfo) {
-        let distance = node_info.get_id().distance(&self.id);
-        self.k_bucket.update_bucket(node_info, distance);
+    /// remember value published by this node, so that it can be published again later
+    pub fn add_publication(&mut self, key: Key, value: Vec<u8>, ttl: Duration) {
+        let publication = Publication {
+            value,
+            ttl,
+            published_at: SystemTime::now(),
+        };
+        self.publications.insert(key, publication);
+    }
+
+    /// key, value and ttl of publications to publish again before they expire on their
+    /// replicas, given the publications are checked every check_interval.
+    /// publications living shorter than `REPUBLISH_ORIGINAL_INTERVAL` are meant to expire, so
+    /// they are forgotten once their ttl runs out instead.
+    pub fn due_publications(&mut self, check_interval: Duration) -> Vec<(Key, Vec<u8>, Duration)> {
+        let now = SystemTime::now();
+        let mut due = Vec::new();
+        self.publications.retain(|k, p| {
+            let age = now.duration_since(p.published_at).unwrap_or_default();
+            if p.ttl < REPUBLISH_ORIGINAL_INTERVAL {
+                return age < p.ttl;
+            }
+            // publish on the last check before it is due
+            if age + check_interval >= REPUBLISH_ORIGINAL_INTERVAL {
+                p.published_at = now;
+                due.push((k.clone(), p.value.clone(), p.ttl));
+            }
+            true
+        });
+        due
+    }
+
+    /// remove expired values, returning the number of removed values
+    pub fn remove_expired(&mut self) -> Result<usize> {
+        self.storage.remove_expired()
+    }
+
+    /// number of contacts in the routing table
+    pub fn contact_count(&self) -> usize {
+        self.k_bucket.len()
+    }
+
+    /// every contact of the routing table
+    pub fn contacts(&self) -> Vec<Contact> {
+        self.k_bucket.contacts()
+    }
+
+    /// routing table of this node, to inspect its buckets and contacts
+    pub fn get_routing_table(&self) -> &KBucket {
+        &self.k_bucket
+    }
+
+    /// serializable copy of the routing table
+    pub fn routing_snapshot(&self) -> RoutingSnapshot {
+        self.k_bucket.snapshot()
+    }
+
+    /// return k closest nodes to given target this node knows of.
+    pub fn find_node(&self, target: &Key) -> Vec<NodeInfo> {
+        self.k_bucket.closest(target, self.config.get_k())
+    }
+
+    /// record a lookup for given target in the bucket it falls into.
+    pub fn touch_bucket(&mut self, target: &Key) {
+        self.k_bucket.touch(target);
+    }
+
+    /// random id in the range of every bucket without any lookup for longer than given duration
+    pub fn idle_buckets(&self, idle: Duration) -> Vec<Key> {
+        self.k_bucket.idle_buckets(idle)
+    }
+
+    /// update bucket with given node.
+    /// returns least-recently seen node of the bucket which must be pinged if the bucket is full.
+    pub fn update_bucket(&mut self, node_info: NodeInfo) -> Option<NodeInfo> {
+        if node_info.get_id() == self.get_id() {
+            return None;
+        }
+        self.k_bucket.update_bucket(node_info)
+    }
+
+    pub fn ping_result(&mut self, head: &NodeInfo, alive: bool) {
+        self.k_bucket.ping_result(head, alive);
+    }
+
+    /// replace given node in its bucket if a replacement is available.
+    pub fn mark_stale(&mut self, node_info: &NodeInfo) {
+        if node_info.get_id() == self.get_id() {
+            return;
+        }
+        self.k_bucket.mark_stale(node_info);
+    }
+}
+
+/// update bucket with given node. if the bucket is full, ping its least-recently seen node and
+/// evict it in favor of a replacement if it doesn't respond in time.
+/// the lock is not held while waiting for the response.
+pub async fn update_contact(node: &RwLock<Node>, node_info: NodeInfo) {
+    let (head, own_info, identity, timeout, transport) = {
+        let mut node = node.write().await;
+        (
+            node.update_bucket(node_info),
+            node.get_info(),
+            node.get_identity(),
+            node.get_config().get_rpc_timeout(),
+            node.get_transport(),
+        )
+    };
+    if let Some(head) = head {
+        let to = *head.reachable_host(&own_info);
+        let mut req = Request::new(Some(own_info), Rpc::Ping, to);
+        req.sign(&identity);
+        let alive = match transport.send(&req, timeout).await {
+            // another node may have taken over the address of head
+            Ok(res) => {
+                res.get_from().get_id() == head.get_id()
+                    && matches!(res.get_body(), Some(ResponseBody::PONG))
+            }
+            Err(_) => false,
+        };
+        node.write().await.ping_result(&head, alive);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::replication::REPUBLISH_INTERVAL};
+
+    #[test]
+    fn test_reachable_host() {
+        let v4: SocketAddr = "127.0.0.1:2000".parse().unwrap();
+        let v6: SocketAddr = "[::1]:2000".parse().unwrap();
+        let local_v4 = NodeInfo::new("127.0.0.1:2001".parse().unwrap(), Key::random());
+        let local_v6 = NodeInfo::new("[::1]:2001".parse().unwrap(), Key::random());
+
+        let dual = NodeInfo::with_alt_host(v6, Some(v4), Key::random());
+        assert_eq!(dual.reachable_host(&local_v4), &v4);
+        assert_eq!(dual.reachable_host(&local_v6), &v6);
+        // no shared family, the main address is tried anyway
+        let v6_only = NodeInfo::new(v6, Key::random());
+        assert_eq!(v6_only.reachable_host(&local_v4), &v6);
+    }
+
+    #[test]
+    fn test_node_info_without_alt_host() {
+        let info = NodeInfo::new("127.0.0.1:2000".parse().unwrap(), Key::random());
+        let json = serde_json::to_string(&info).unwrap();
+        assert!(!json.contains("alt_host"));
+        assert_eq!(serde_json::from_str::<NodeInfo>(&json).unwrap(), info);
+    }
+
+    #[test]
+    fn test_due_publications() {
+        let mut node = Node::new("127.0.0.1:2000".parse().unwrap()).unwrap();
+        node.add_publication("k1".into(), b"v1".to_vec(), REPUBLISH_ORIGINAL_INTERVAL);
+        node.add_publication("k2".into(), b"v2".to_vec(), Duration::from_secs(0));
+        assert!(node.due_publications(REPUBLISH_INTERVAL).is_empty());
+
+        // short lived publication is forgotten, long lived one is due before it expires
+        let due = node.due_publications(REPUBLISH_ORIGINAL_INTERVAL);
+        assert_eq!(
+            due,
+            vec![("k1".into(), b"v1".to_vec(), REPUBLISH_ORIGINAL_INTERVAL)]
+        );
+        assert!(node.due_publications(REPUBLISH_INTERVAL).is_empty());
     }
 }
@@ -0,0 +1,30 @@
+use {
+    crate::{error::Result, lookup::lookup_nodes, node::Node},
+    async_std::{sync::RwLock, task},
+    std::{sync::Arc, time::Duration},
+};
+
+/// buckets without any lookup for this long are refreshed
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// look up a random id in the range of every bucket idle for longer than given interval.
+/// returns number of refreshed buckets.
+pub async fn refresh_idle_buckets(node: &Arc<RwLock<Node>>, interval: Duration) -> Result<usize> {
+    let idle = node.read().await.idle_buckets(interval);
+    for id in idle.iter() {
+        // the lookup marks the bucket as used
+        lookup_nodes(node, id).await?;
+    }
+    Ok(idle.len())
+}
+
+/// check for idle buckets every given interval and refresh them.
+pub async fn refresh_loop(node: Arc<RwLock<Node>>, interval: Duration) {
+    loop {
+        task::sleep(interval).await;
+        match refresh_idle_buckets(&node, interval).await {
+            Ok(count) => println!("Refreshed {} idle buckets", count),
+            Err(e) => println!("Bucket refresh fail: {}", e),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use {
+    crate::{
+        error::{Error, Result},
+        key::Key,
+        lookup::lookup_nodes,
+        node::{Node, NodeInfo},
+        request::Request,
+        response::ResponseBody,
+        rpc::Rpc,
+    },
+    async_std::{sync::RwLock, task},
+    std::{sync::Arc, time::Duration},
+};
+
+/// default number of replicas a put needs to succeed.
+/// two replicas are enough to survive the loss of any single node.
+pub const DEFAULT_MIN_REPLICAS: usize = 2;
+
+/// how often values held by this node are stored again at the k closest nodes to their key
+pub const REPUBLISH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// how often the original publisher of a value publishes it again, renewing its ttl
+pub const REPUBLISH_ORIGINAL_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// outcome of a put, holding STORE result for every replica contacted
+#[derive(Debug)]
+pub struct PutResult {
+    replicas: Vec<(NodeInfo, Result<()>)>,
+    min_replicas: usize,
+}
+
+impl PutResult {
+    pub fn get_replicas(&self) -> &Vec<(NodeInfo, Result<()>)> {
+        &self.replicas
+    }
+
+    /// number of replicas which stored the value
+    pub fn stored(&self) -> usize {
+        self.replicas.iter().filter(|(_, r)| r.is_ok()).count()
+    }
+
+    /// put is successful when at least min_replicas nodes stored the value
+    pub fn is_success(&self) -> bool {
+        self.stored() >= self.min_replicas
+    }
+}
+
+/// look up k closest nodes to the key and send STORE to each of them.
+/// this node stores the value as well if it is one of the k closest nodes to the key.
+/// the value expires after ttl on every replica, unless this node publishes it again, which it
+/// only does for successful puts.
+pub async fn put(
+    node: &Arc<RwLock<Node>>,
+    key: Key,
+    value: Vec<u8>,
+    ttl: Duration,
+    min_replicas: usize,
+) -> Result<PutResult> {
+    let res = replicate(node, key.clone(), value.clone(), ttl, min_replicas).await?;
+    if res.is_success() {
+        node.write().await.add_publication(key, value, ttl);
+    }
+    Ok(res)
+}
+
+/// store values again at the k closest nodes to their key, which may have changed since they
+/// were stored.
+/// 1. publications of this node due to be renewed are published with their full ttl.
+/// 2. values held by this node are stored with their remaining ttl, except the ones received
+///    within the last interval, since the node which sent them republished them already.
+///
+/// returns number of republished values.
+pub async fn republish(node: &Arc<RwLock<Node>>, interval: Duration) -> usize {
+    let (publications, held, min_replicas) = {
+        let mut node = node.write().await;
+        (
+            node.due_publications(interval),
+            node.stored_before(interval),
+            node.get_config().get_min_replicas(),
+        )
+    };
+    let mut count = 0;
+    for (key, value, ttl) in publications.into_iter().chain(held) {
+        match replicate(node, key.clone(), value, ttl, min_replicas).await {
+            Ok(_) => count += 1,
+            Err(e) => println!("Republish of {:?} fail: {}", key, e),
+        }
+    }
+    count
+}
+
+/// republish values every given interval.
+pub async fn republish_loop(node: Arc<RwLock<Node>>, interval: Duration) {
+    loop {
+        task::sleep(interval).await;
+        let count = republish(&node, interval).await;
+        println!("Republished {} values", count);
+    }
+}
+
+async fn replicate(
+    node: &Arc<RwLock<Node>>,
+    key: Key,
+    value: Vec<u8>,
+    ttl: Duration,
+    min_replicas: usize,
+) -> Result<PutResult> {
+    let closest = lookup_nodes(node, &key).await?;
+    let (own_info, identity, config, transport) = {
+        let node = node.read().await;
+        (
+            node.get_info(),
+            node.get_identity(),
+            node.get_config().clone(),
+            node.get_transport(),
+        )
+    };
+
+    let mut replicas = Vec::new();
+    let own_distance = own_info.get_id().distance(&key);
+    let is_closest = match closest.get(config.get_k() - 1) {
+        Some(farthest) => own_distance < farthest.get_id().distance(&key),
+        None => true,
+    };
+    if is_closest {
+        let res = node.write().await.store(key.clone(), value.clone(), ttl);
+        replicas.push((own_info.clone(), res));
+    }
+
+    let handles: Vec<_> = closest
+        .into_iter()
+        .map(|to| {
+            let mut req = Request::new(
+                Some(own_info.clone()),
+                Rpc::Store(key.clone(), value.clone(), Some(ttl)),
+                *to.reachable_host(&own_info),
+            );
+            req.sign(&identity);
+            let transport = transport.clone();
+            let timeout = config.get_rpc_timeout();
+            task::spawn(async move {
+                let res =
+                    transport
+                        .send(&req, timeout)
+                        .await
+                        .and_then(|res| match res.get_body() {
+                            Some(ResponseBody::STORED) => Ok(()),
+                            body => Err(Error::UnexpectedResponse(format!("{:?}", body))),
+                        });
+                (to, res)
+            })
+        })
+        .collect();
+    for handle in handles {
+        replicas.push(handle.await);
+    }
+
+    Ok(PutResult {
+        replicas,
+        min_replicas,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_node_info(port: u16) -> NodeInfo {
+        let host = format!("127.0.0.1:{}", port).parse().unwrap();
+        NodeInfo::new(host, Key::random())
+    }
+
+    #[test]
+    fn test_put_result_success() {
+        let res = PutResult {
+            replicas: vec![
+                (create_node_info(2000), Ok(())),
+                (
+                    create_node_info(2001),
+                    Err(Error::UnexpectedResponse("None".to_owned())),
+                ),
+                (create_node_info(2002), Ok(())),
+            ],
+            min_replicas: DEFAULT_MIN_REPLICAS,
+        };
+        assert_eq!(res.stored(), 2);
+        assert!(res.is_success());
+    }
+
+    #[test]
+    fn test_put_result_not_enough_replicas() {
+        let res = PutResult {
+            replicas: vec![
+                (create_node_info(2000), Ok(())),
+                (
+                    create_node_info(2001),
+                    Err(Error::UnexpectedResponse("None".to_owned())),
+                ),
+            ],
+            min_replicas: DEFAULT_MIN_REPLICAS,
+        };
+        assert_eq!(res.stored(), 1);
+        assert!(!res.is_success());
+    }
+}
@@ -1,20 +1,61 @@
 use {
-    crate::{error::Result, node::NodeInfo, response::Response, rpc::Rpc},
-    async_std::{net::TcpStream, prelude::*},
+    crate::{
+        error::{Error, Result},
+        identity::{signed_bytes, Identity, Signature},
+        node::NodeInfo,
+        rpc::Rpc,
+    },
+    ring::rand::{SecureRandom, SystemRandom},
     serde::{Deserialize, Serialize},
-    std::net::Shutdown,
+    std::{fmt, net::SocketAddr, time::Duration},
 };
 
+/// time to wait for a response before treating the remote node as unresponsive
+pub const RPC_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// random transaction identifier of a request, echoed back in its response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RequestId(u64);
+
+impl RequestId {
+    pub fn random() -> Self {
+        let mut bytes = [0u8; 8];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .expect("failed to generate random request id");
+        Self(u64::from_be_bytes(bytes))
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Request {
+    id: RequestId,
     from: Option<NodeInfo>,
-    to: NodeInfo,
+    to: SocketAddr,
     rpc: Rpc,
+    signature: Option<Signature>,
 }
 
 impl Request {
-    pub fn new(from: Option<NodeInfo>, rpc: Rpc, to: NodeInfo) -> Self {
-        Self { from, rpc, to }
+    /// request to the node at given address, whose id may not be known yet
+    pub fn new(from: Option<NodeInfo>, rpc: Rpc, to: SocketAddr) -> Self {
+        Self {
+            id: RequestId::random(),
+            from,
+            rpc,
+            to,
+            signature: None,
+        }
+    }
+
+    pub fn get_id(&self) -> RequestId {
+        self.id
     }
 
     pub fn get_from(&self) -> Option<&NodeInfo> {
@@ -25,20 +66,25 @@ impl Request {
         &self.rpc
     }
 
-    pub fn get_to(&self) -> &NodeInfo {
+    pub fn get_to(&self) -> &SocketAddr {
         &self.to
     }
 
-    pub async fn send(&self) -> Result<Response> {
-        let req_str = serde_json::to_string(&self)?;
-        let mut stream = TcpStream::connect(self.to.get_host()).await?;
-        stream.write_all(req_str.as_bytes()).await?;
-        stream.write("\n".as_bytes()).await?;
-        let mut buf = vec![0u8; 32];
-        let count = stream.read(&mut buf).await?;
-        stream.shutdown(Shutdown::Both)?;
-        let res_str = String::from_utf8(buf[..count].to_vec())?.trim().to_owned();
-        let res: Response = serde_json::from_str(&res_str)?;
-        Ok(res)
+    /// sign the request with the keypair of the sending node
+    pub fn sign(&mut self, identity: &Identity) {
+        self.signature = Some(identity.sign(&signed_bytes(self)));
+    }
+
+    /// check that the request was signed by the node it claims to be from.
+    /// requests without sender come from clients outside the network and may be unsigned.
+    pub fn verify(&self) -> Result<()> {
+        let from = match &self.from {
+            Some(from) => from,
+            None => return Ok(()),
+        };
+        match &self.signature {
+            Some(signature) => signature.verify(from.get_id(), &signed_bytes(self)),
+            None => Err(Error::InvalidSignature("request is not signed".to_owned())),
+        }
     }
 }
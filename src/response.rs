@@ -1,38 +1,60 @@
 use {
-    crate::{node::NodeInfo, request::Request, rpc::Rpc},
-    arrayvec::ArrayVec,
+    crate::{
+        error::{Error, Result},
+        identity::{signed_bytes, Identity, Signature},
+        node::NodeInfo,
+        request::{Request, RequestId},
+        rpc::Rpc,
+    },
     serde::{Deserialize, Serialize},
 };
 
-const K: usize = 10;
-
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ResponseBody {
     PONG,
     VALUE(Vec<u8>),
     NODES(Vec<NodeInfo>),
+    STORED,
+    ERROR(String),
+    /// response doesn't fit in a datagram, request must be resent over TCP
+    TRUNCATED,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Response {
-    request_rpc: Rpc,
+    request_id: Option<RequestId>,
+    request_rpc: Option<Rpc>,
     from: NodeInfo,
     to: Option<NodeInfo>,
     body: Option<ResponseBody>,
+    signature: Option<Signature>,
 }
 
 impl Response {
-    pub fn new(from: NodeInfo, to: Option<NodeInfo>, rpc: Rpc, body: Option<ResponseBody>) -> Self {
+    pub fn new(
+        from: NodeInfo,
+        to: Option<NodeInfo>,
+        rpc: Option<Rpc>,
+        body: Option<ResponseBody>,
+    ) -> Self {
         Self {
+            request_id: None,
             from,
             to,
             request_rpc: rpc,
             body,
+            signature: None,
         }
     }
 
-    pub fn get_request_rpc(&self) -> &Rpc {
-        &self.request_rpc
+    /// id of the request this response answers, None if the request could not be parsed
+    pub fn get_request_id(&self) -> Option<RequestId> {
+        self.request_id
+    }
+
+    /// rpc of the request this response answers, None if the request could not be parsed
+    pub fn get_request_rpc(&self) -> Option<&Rpc> {
+        self.request_rpc.as_ref()
     }
 
     pub fn get_from(&self) -> &NodeInfo {
@@ -51,12 +73,28 @@ impl Response {
         self.body = body
     }
 
-    pub fn from_request(req: Request) -> Self {
+    /// empty response from given node to given request
+    pub fn from_request(req: &Request, from: NodeInfo) -> Self {
         Self {
-            from: req.get_to().clone(),
+            request_id: Some(req.get_id()),
+            from,
             to: req.get_from().map(|f| f.clone()),
-            request_rpc: req.get_rpc().clone(),
+            request_rpc: Some(req.get_rpc().clone()),
             body: None,
+            signature: None,
+        }
+    }
+
+    /// sign the response with the keypair of the responding node, once its body is set
+    pub fn sign(&mut self, identity: &Identity) {
+        self.signature = Some(identity.sign(&signed_bytes(self)));
+    }
+
+    /// check that the response was signed by the node it claims to be from
+    pub fn verify(&self) -> Result<()> {
+        match &self.signature {
+            Some(signature) => signature.verify(self.from.get_id(), &signed_bytes(self)),
+            None => Err(Error::InvalidSignature("response is not signed".to_owned())),
         }
     }
 }
@@ -1,6 +1,7 @@
 use {
     crate::key::Key,
     serde::{Deserialize, Serialize},
+    std::time::Duration,
 };
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
@@ -11,10 +12,11 @@ pub enum Rpc {
 
     /// STORE is used to store given key value pair
     /// request string must be a shape of following
-    /// `STORE <key> <value>`
+    /// `STORE <key> <value> [<ttl>]`
     /// <key> must be 160-bit data represented as bytes of length 20.
     /// <value> can be represented as any kind of data but parsed into Vec<u8>
-    Store(Key, Vec<u8>),
+    /// <ttl> is how long the value lives, `DEFAULT_TTL` if not given.
+    Store(Key, Vec<u8>, Option<Duration>),
 
     /// FIND_NODE is used to find closest nodes with given 160-bit id.
     /// request string must be a shape of following
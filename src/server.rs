@@ -0,0 +1,245 @@
+use {
+    crate::{
+        error::{Error, Result},
+        frame::{read_frame, write_message},
+        in_memory_hash_table::DEFAULT_TTL,
+        node::{update_contact, Node},
+        request::Request,
+        response::{Response, ResponseBody},
+        rpc::Rpc,
+    },
+    async_std::{
+        net::{TcpListener, TcpStream},
+        prelude::*,
+        sync::RwLock,
+        task,
+    },
+    std::sync::Arc,
+};
+
+/// accept connections on given listener and serve each of them in its own task.
+/// frames larger than max_frame_size are rejected.
+pub async fn serve(
+    listener: TcpListener,
+    node: Arc<RwLock<Node>>,
+    max_frame_size: usize,
+) -> Result<()> {
+    let mut incoming = listener.incoming();
+    while let Some(Ok(stream)) = incoming.next().await {
+        let node = node.clone();
+        task::spawn(async move { connection_loop(stream, node, max_frame_size).await });
+    }
+    Ok(())
+}
+
+/// read length-prefixed requests from given stream and write a response frame to each.
+/// requests which cannot be deserialized are answered with an ERROR body.
+/// an oversized frame is answered with an ERROR body and closes the connection, since the stream
+/// cannot be resynchronized after it.
+pub async fn connection_loop(
+    mut stream: TcpStream,
+    node: Arc<RwLock<Node>>,
+    max_frame_size: usize,
+) -> Result<()> {
+    println!("Incoming stream from '{:?}'", stream.peer_addr()?);
+    loop {
+        let frame = match read_frame(&mut stream, max_frame_size).await {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return Ok(()),
+            Err(e @ Error::FrameTooLarge(..)) => {
+                let res = error_response(&node, format!("{}", e)).await;
+                write_message(&mut stream, &res, max_frame_size).await?;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+        let res = match serde_json::from_slice::<Request>(&frame) {
+            Ok(req) => {
+                println!("{:?}", req);
+                handle_request(&node, &req).await
+            }
+            Err(e) => {
+                println!("Request deserialize fail: {:?}", e);
+                error_response(&node, format!("invalid request: {}", e)).await
+            }
+        };
+        write_message(&mut stream, &res, max_frame_size).await?;
+    }
+}
+
+/// response to a request which could not be read
+async fn error_response(node: &RwLock<Node>, msg: String) -> Response {
+    let (from, identity) = {
+        let node = node.read().await;
+        (node.get_info(), node.get_identity())
+    };
+    let mut res = Response::new(from, None, None, Some(ResponseBody::ERROR(msg)));
+    res.sign(&identity);
+    res
+}
+
+/// handle given request and build the signed response to it.
+/// sender of the request is added to our buckets.
+/// a request not signed by the node it claims to be from is answered with an ERROR body.
+pub async fn handle_request(node: &Arc<RwLock<Node>>, req: &Request) -> Response {
+    if let Err(e) = req.verify() {
+        println!("Request {} rejected: {}", req.get_id(), e);
+        node.read().await.get_counters().record_rejected();
+        return reply(node, req, ResponseBody::ERROR(format!("{}", e))).await;
+    }
+
+    node.read().await.get_counters().record(req.get_rpc());
+    let body = match req.get_rpc() {
+        Rpc::Ping => ResponseBody::PONG,
+        Rpc::FindValue(k) => {
+            let node = node.read().await;
+            match node.find_value(k) {
+                Some(v) => ResponseBody::VALUE(v),
+                None => ResponseBody::NODES(node.find_node(k)),
+            }
+        }
+        Rpc::FindNode(k) => ResponseBody::NODES(node.read().await.find_node(k)),
+        Rpc::Store(k, v, ttl) => {
+            let ttl = ttl.unwrap_or(DEFAULT_TTL);
+            match node.write().await.store(k.clone(), v.clone(), ttl) {
+                Ok(()) => ResponseBody::STORED,
+                Err(e) => ResponseBody::ERROR(format!("store failed: {}", e)),
+            }
+        }
+    };
+
+    if let Some(n) = req.get_from() {
+        let node = node.clone();
+        let n = n.clone();
+        task::spawn(async move { update_contact(&node, n).await });
+    }
+
+    reply(node, req, body).await
+}
+
+/// signed response to given request with given body
+async fn reply(node: &RwLock<Node>, req: &Request, body: ResponseBody) -> Response {
+    let (own_info, identity) = {
+        let node = node.read().await;
+        (node.get_info(), node.get_identity())
+    };
+    let mut res = Response::from_request(req, own_info);
+    res.set_body(Some(body));
+    res.sign(&identity);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {
+        crate::{identity::Identity, node::NodeInfo},
+        async_std::task::block_on,
+        std::{net::SocketAddr, time::Duration},
+    };
+
+    fn create_node() -> (Arc<RwLock<Node>>, SocketAddr) {
+        let host: SocketAddr = "127.0.0.1:2000".parse().unwrap();
+        (Arc::new(RwLock::new(Node::new(host).unwrap())), host)
+    }
+
+    #[test]
+    fn test_handle_ping() {
+        let (node, info) = create_node();
+        let res = block_on(handle_request(&node, &Request::new(None, Rpc::Ping, info)));
+        assert!(matches!(res.get_body(), Some(ResponseBody::PONG)));
+        assert_eq!(res.get_request_rpc(), Some(&Rpc::Ping));
+        // response carries our own id, not one derived from the address
+        assert_eq!(res.get_from(), &block_on(node.read()).get_info());
+        assert!(res.verify().is_ok());
+    }
+
+    #[test]
+    fn test_handle_signed_request() {
+        let (node, info) = create_node();
+        let sender = Identity::from_seed(&[1; 32]).unwrap();
+        let from = NodeInfo::new("127.0.0.1:2001".parse().unwrap(), sender.get_id().clone());
+        let mut req = Request::new(Some(from), Rpc::Ping, info);
+        req.sign(&sender);
+        let res = block_on(handle_request(&node, &req));
+        assert!(matches!(res.get_body(), Some(ResponseBody::PONG)));
+    }
+
+    #[test]
+    fn test_spoofed_request_rejected() {
+        let (node, info) = create_node();
+        let sender = Identity::from_seed(&[1; 32]).unwrap();
+        let victim = Identity::from_seed(&[2; 32]).unwrap();
+        let from = NodeInfo::new("127.0.0.1:2001".parse().unwrap(), victim.get_id().clone());
+
+        // claiming the id of another node
+        let mut req = Request::new(Some(from.clone()), Rpc::Ping, info);
+        req.sign(&sender);
+        let res = block_on(handle_request(&node, &req));
+        assert!(matches!(res.get_body(), Some(ResponseBody::ERROR(_))));
+        assert!(res.verify().is_ok());
+
+        // not signed at all
+        let req = Request::new(Some(from), Rpc::Ping, info);
+        let res = block_on(handle_request(&node, &req));
+        assert!(matches!(res.get_body(), Some(ResponseBody::ERROR(_))));
+    }
+
+    #[test]
+    fn test_tampered_request_rejected() {
+        let (node, info) = create_node();
+        let sender = Identity::from_seed(&[1; 32]).unwrap();
+        let from = NodeInfo::new("127.0.0.1:2001".parse().unwrap(), sender.get_id().clone());
+        let mut req = Request::new(
+            Some(from),
+            Rpc::Store("k1".into(), b"v1".to_vec(), None),
+            info,
+        );
+        req.sign(&sender);
+        let mut json = serde_json::to_value(&req).unwrap();
+        json["rpc"]["Store"][1] = serde_json::to_value(b"v2").unwrap();
+        let req: Request = serde_json::from_value(json).unwrap();
+
+        let res = block_on(handle_request(&node, &req));
+        assert!(matches!(res.get_body(), Some(ResponseBody::ERROR(_))));
+        assert_eq!(block_on(node.read()).find_value(&"k1".into()), None);
+    }
+
+    #[test]
+    fn test_handle_store_and_find_value() {
+        let (node, info) = create_node();
+        let store = Request::new(None, Rpc::Store("k1".into(), b"v1".to_vec(), None), info);
+        let res = block_on(handle_request(&node, &store));
+        assert!(matches!(res.get_body(), Some(ResponseBody::STORED)));
+
+        let find = Request::new(None, Rpc::FindValue("k1".into()), info);
+        let res = block_on(handle_request(&node, &find));
+        match res.get_body() {
+            Some(ResponseBody::VALUE(v)) => assert_eq!(v, &b"v1".to_vec()),
+            body => panic!("unexpected body {:?}", body),
+        }
+    }
+
+    #[test]
+    fn test_handle_store_with_ttl() {
+        let (node, info) = create_node();
+        let ttl = Some(Duration::from_secs(0));
+        let store = Request::new(None, Rpc::Store("k1".into(), b"v1".to_vec(), ttl), info);
+        let res = block_on(handle_request(&node, &store));
+        assert!(matches!(res.get_body(), Some(ResponseBody::STORED)));
+
+        // already expired
+        let find = Request::new(None, Rpc::FindValue("k1".into()), info);
+        let res = block_on(handle_request(&node, &find));
+        assert!(matches!(res.get_body(), Some(ResponseBody::NODES(_))));
+        assert_eq!(block_on(node.write()).remove_expired().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_handle_find_value_missing_returns_nodes() {
+        let (node, info) = create_node();
+        let find = Request::new(None, Rpc::FindValue("k1".into()), info);
+        let res = block_on(handle_request(&node, &find));
+        assert!(matches!(res.get_body(), Some(ResponseBody::NODES(_))));
+    }
+}
@@ -0,0 +1,24 @@
+use {
+    crate::{error::Result, key::Key},
+    std::time::Duration,
+};
+
+/// values stored by a node, each living for a ttl from when it was stored
+pub trait Storage: Send + Sync {
+    /// get value for given key, unless it has expired
+    fn get(&self, key: &Key) -> Option<&Vec<u8>>;
+
+    /// store value for given key, living for ttl from now.
+    /// returns the previous value if it has not expired.
+    fn put(&mut self, key: Key, value: Vec<u8>, ttl: Duration) -> Result<Option<Vec<u8>>>;
+
+    /// key, value and remaining ttl of every value stored more than given duration ago which
+    /// has not expired yet
+    fn stored_before(&self, age: Duration) -> Vec<(Key, Vec<u8>, Duration)>;
+
+    /// key, size in bytes and remaining ttl of every value which has not expired yet
+    fn entries(&self) -> Vec<(Key, usize, Duration)>;
+
+    /// remove every expired value, returning the number of removed values
+    fn remove_expired(&mut self) -> Result<usize>;
+}
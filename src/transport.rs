@@ -0,0 +1,82 @@
+use {
+    crate::{
+        client::Connection,
+        error::{Error, Result},
+        request::Request,
+        response::Response,
+        udp,
+    },
+    async_std::future,
+    async_trait::async_trait,
+    std::{str::FromStr, time::Duration},
+};
+
+/// Transport sends a request to the node given as its destination and returns the response.
+/// implementations must fail with `Error::Timeout` if no response arrives within given duration.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn send(&self, req: &Request, timeout: Duration) -> Result<Response>;
+}
+
+/// opens a new TCP connection for every request
+pub struct TcpTransport {
+    max_frame_size: usize,
+}
+
+impl TcpTransport {
+    pub fn new(max_frame_size: usize) -> Self {
+        Self { max_frame_size }
+    }
+}
+
+#[async_trait]
+impl Transport for TcpTransport {
+    async fn send(&self, req: &Request, timeout: Duration) -> Result<Response> {
+        future::timeout(timeout, async {
+            let conn = Connection::connect(req.get_to(), self.max_frame_size).await?;
+            conn.send(req).await
+        })
+        .await?
+    }
+}
+
+/// sends every request in a datagram, falling back to TCP for payloads exceeding the
+/// datagram limit
+pub struct UdpTransport {
+    retries: u32,
+    fallback: TcpTransport,
+}
+
+impl UdpTransport {
+    pub fn new(retries: u32, fallback: TcpTransport) -> Self {
+        Self { retries, fallback }
+    }
+}
+
+#[async_trait]
+impl Transport for UdpTransport {
+    async fn send(&self, req: &Request, timeout: Duration) -> Result<Response> {
+        udp::send(req, timeout, self.retries, &self.fallback).await
+    }
+}
+
+/// protocol used to send requests to other nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// a new TCP connection for every request
+    Tcp,
+    /// a datagram for every request, TCP for payloads exceeding the datagram limit
+    Udp,
+}
+
+impl FromStr for Protocol {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tcp" => Ok(Protocol::Tcp),
+            "udp" => Ok(Protocol::Udp),
+            _ => Err(Error::InvalidRequest(format!("unknown protocol {}", s))),
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use {
+    crate::{
+        error::{Error, Result},
+        node::Node,
+        request::Request,
+        response::{Response, ResponseBody},
+        server::handle_request,
+        transport::{TcpTransport, Transport},
+    },
+    async_std::{future, net::UdpSocket, sync::RwLock, task},
+    std::{
+        net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+        sync::Arc,
+        time::{Duration, Instant},
+    },
+};
+
+/// largest request or response sent in a single datagram.
+/// bigger payloads are sent over TCP instead.
+pub const MAX_DATAGRAM_SIZE: usize = 8192;
+
+/// number of times a request is resent when no response arrives
+pub const UDP_RETRIES: u32 = 2;
+
+/// largest number of resends accepted in a config, each attempt getting a share of the timeout
+pub const MAX_UDP_RETRIES: u32 = 100;
+
+/// send request in a datagram and wait for the response with the same request id.
+/// the request is resent up to `retries` times, each attempt waiting an equal share of `timeout`.
+/// falls back to given TCP transport if either the request or its response doesn't fit in a
+/// datagram, within what is left of `timeout`.
+pub async fn send(
+    req: &Request,
+    timeout: Duration,
+    retries: u32,
+    fallback: &TcpTransport,
+) -> Result<Response> {
+    let payload = serde_json::to_vec(req)?;
+    if payload.len() > MAX_DATAGRAM_SIZE {
+        return fallback.send(req, timeout).await;
+    }
+
+    let to = *req.get_to();
+    let local: SocketAddr = if to.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local).await?;
+    let start = Instant::now();
+    let attempt_timeout = timeout / (retries + 1);
+    let mut attempt = 0;
+    loop {
+        socket.send_to(&payload, to).await?;
+        match future::timeout(attempt_timeout, recv_response(&socket, req, to)).await {
+            Ok(res) => {
+                let res = res?;
+                if let Some(ResponseBody::TRUNCATED) = res.get_body() {
+                    let remaining = timeout.checked_sub(start.elapsed()).unwrap_or_default();
+                    return fallback.send(req, remaining).await;
+                }
+                return Ok(res);
+            }
+            Err(e) if attempt == retries => return Err(e.into()),
+            Err(_) => attempt += 1,
+        }
+    }
+}
+
+/// wait for the response to given request, discarding datagrams from other peers, answering
+/// other requests or not signed by the node they claim to be from, since the source address of a
+/// datagram is easily forged.
+async fn recv_response(socket: &UdpSocket, req: &Request, to: SocketAddr) -> Result<Response> {
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let (count, peer) = socket.recv_from(&mut buf).await?;
+        if peer != to {
+            continue;
+        }
+        let res: Response = match serde_json::from_slice(&buf[..count]) {
+            Ok(res) => res,
+            Err(e) => {
+                println!("Response deserialize fail: {:?}", e);
+                continue;
+            }
+        };
+        if res.get_request_id() != Some(req.get_id()) {
+            println!("Unsolicited response discarded: {:?}", res);
+            continue;
+        }
+        if let Err(e) = res.verify() {
+            println!("Response to request {} discarded: {}", req.get_id(), e);
+            continue;
+        }
+        if res.get_request_rpc() != Some(req.get_rpc()) {
+            return Err(Error::UnexpectedResponse(format!(
+                "response to request {} answers {:?}, expected {:?}",
+                req.get_id(),
+                res.get_request_rpc(),
+                req.get_rpc()
+            )));
+        }
+        return Ok(res);
+    }
+}
+
+/// receive requests on given socket and answer each with a datagram.
+/// a response which doesn't fit in a datagram is replaced with TRUNCATED, telling the client to
+/// retry over TCP.
+pub async fn serve(socket: UdpSocket, node: Arc<RwLock<Node>>) -> Result<()> {
+    let socket = Arc::new(socket);
+    let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let (count, peer) = socket.recv_from(&mut buf).await?;
+        let req: Request = match serde_json::from_slice(&buf[..count]) {
+            Ok(req) => req,
+            Err(e) => {
+                println!("Request deserialize fail: {:?}", e);
+                continue;
+            }
+        };
+        let socket = socket.clone();
+        let node = node.clone();
+        task::spawn(async move {
+            if let Err(e) = respond(&socket, peer, &node, &req).await {
+                println!("Failed to respond to {}: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn respond(
+    socket: &UdpSocket,
+    peer: SocketAddr,
+    node: &Arc<RwLock<Node>>,
+    req: &Request,
+) -> Result<()> {
+    println!("{:?}", req);
+    let res = handle_request(node, req).await;
+    let mut payload = serde_json::to_vec(&res)?;
+    if payload.len() > MAX_DATAGRAM_SIZE {
+        let mut res = Response::from_request(req, res.get_from().clone());
+        res.set_body(Some(ResponseBody::TRUNCATED));
+        res.sign(&node.read().await.get_identity());
+        payload = serde_json::to_vec(&res)?;
+    }
+    socket.send_to(&payload, peer).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {
+        crate::{
+            frame::DEFAULT_MAX_FRAME_SIZE, identity::Identity, in_memory_hash_table::DEFAULT_TTL,
+            node::NodeInfo, rpc::Rpc, server,
+        },
+        async_std::{net::TcpListener, task::block_on},
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn fallback() -> TcpTransport {
+        TcpTransport::new(DEFAULT_MAX_FRAME_SIZE)
+    }
+
+    /// start a node serving both TCP and UDP on the same port of given address
+    async fn start_node(addr: &str) -> (Arc<RwLock<Node>>, SocketAddr) {
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let host = listener.local_addr().unwrap();
+        let socket = UdpSocket::bind(host).await.unwrap();
+        let node = Arc::new(RwLock::new(Node::new(host).unwrap()));
+        task::spawn(server::serve(
+            listener,
+            node.clone(),
+            DEFAULT_MAX_FRAME_SIZE,
+        ));
+        task::spawn(serve(socket, node.clone()));
+        (node, host)
+    }
+
+    #[test]
+    fn test_ping_over_udp() {
+        block_on(async {
+            let (_, to) = start_node("127.0.0.1:0").await;
+            let req = Request::new(None, Rpc::Ping, to);
+            let res = send(&req, TIMEOUT, UDP_RETRIES, &fallback()).await.unwrap();
+            assert_eq!(res.get_request_id(), Some(req.get_id()));
+            assert!(matches!(res.get_body(), Some(ResponseBody::PONG)));
+        });
+    }
+
+    #[test]
+    fn test_ping_over_udp_ipv6() {
+        block_on(async {
+            let (_, to) = start_node("[::1]:0").await;
+            let req = Request::new(None, Rpc::Ping, to);
+            let res = send(&req, TIMEOUT, UDP_RETRIES, &fallback()).await.unwrap();
+            assert!(matches!(res.get_body(), Some(ResponseBody::PONG)));
+        });
+    }
+
+    #[test]
+    fn test_large_response_falls_back_to_tcp() {
+        block_on(async {
+            let (node, to) = start_node("127.0.0.1:0").await;
+            let value = vec![1u8; MAX_DATAGRAM_SIZE];
+            node.write()
+                .await
+                .store("k1".into(), value.clone(), DEFAULT_TTL)
+                .unwrap();
+
+            let req = Request::new(None, Rpc::FindValue("k1".into()), to);
+            let res = send(&req, TIMEOUT, UDP_RETRIES, &fallback()).await.unwrap();
+            match res.get_body() {
+                Some(ResponseBody::VALUE(v)) => assert_eq!(v, &value),
+                body => panic!("unexpected body {:?}", body),
+            }
+        });
+    }
+
+    #[test]
+    fn test_large_request_falls_back_to_tcp() {
+        block_on(async {
+            let (node, to) = start_node("127.0.0.1:0").await;
+            let value = vec![1u8; MAX_DATAGRAM_SIZE];
+            let req = Request::new(None, Rpc::Store("k1".into(), value.clone(), None), to);
+            let res = send(&req, TIMEOUT, UDP_RETRIES, &fallback()).await.unwrap();
+            assert!(matches!(res.get_body(), Some(ResponseBody::STORED)));
+            assert_eq!(node.read().await.find_value(&"k1".into()), Some(value));
+        });
+    }
+
+    #[test]
+    fn test_request_resent_when_datagram_lost() {
+        block_on(async {
+            // fake server which drops the first datagram and answers the second one
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let host = socket.local_addr().unwrap();
+            task::spawn(async move {
+                let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+                let _ = socket.recv_from(&mut buf).await.unwrap();
+                let (count, peer) = socket.recv_from(&mut buf).await.unwrap();
+                let req: Request = serde_json::from_slice(&buf[..count]).unwrap();
+                let identity = Identity::from_seed(&[1; 32]).unwrap();
+                let from = NodeInfo::new(host, identity.get_id().clone());
+                let mut res = Response::from_request(&req, from);
+                res.set_body(Some(ResponseBody::PONG));
+                res.sign(&identity);
+                let payload = serde_json::to_vec(&res).unwrap();
+                socket.send_to(&payload, peer).await.unwrap();
+            });
+
+            let req = Request::new(None, Rpc::Ping, host);
+            let res = send(&req, TIMEOUT, UDP_RETRIES, &fallback()).await.unwrap();
+            assert!(matches!(res.get_body(), Some(ResponseBody::PONG)));
+        });
+    }
+
+    #[test]
+    fn test_fallback_within_timeout() {
+        block_on(async {
+            // fake server which drops the first datagrams and answers the last attempt with
+            // TRUNCATED, over a TCP port which never answers
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let host = socket.local_addr().unwrap();
+            let _listener = TcpListener::bind(host).await.unwrap();
+            task::spawn(async move {
+                let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+                for _ in 0..UDP_RETRIES {
+                    let _ = socket.recv_from(&mut buf).await.unwrap();
+                }
+                let (count, peer) = socket.recv_from(&mut buf).await.unwrap();
+                let req: Request = serde_json::from_slice(&buf[..count]).unwrap();
+                let identity = Identity::from_seed(&[1; 32]).unwrap();
+                let from = NodeInfo::new(host, identity.get_id().clone());
+                let mut res = Response::from_request(&req, from);
+                res.set_body(Some(ResponseBody::TRUNCATED));
+                res.sign(&identity);
+                let payload = serde_json::to_vec(&res).unwrap();
+                socket.send_to(&payload, peer).await.unwrap();
+            });
+
+            let timeout = Duration::from_millis(1500);
+            let start = Instant::now();
+            let req = Request::new(None, Rpc::Ping, host);
+            let res = send(&req, timeout, UDP_RETRIES, &fallback()).await;
+            assert!(matches!(res, Err(Error::Timeout(_))));
+            // the fallback only gets the third of the timeout left after two attempts
+            assert!(start.elapsed() < timeout + timeout / 3);
+        });
+    }
+
+    #[test]
+    fn test_timeout_without_response() {
+        block_on(async {
+            // bound but never answering
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let host = socket.local_addr().unwrap();
+
+            let req = Request::new(None, Rpc::Ping, host);
+            let res = send(&req, Duration::from_millis(300), UDP_RETRIES, &fallback()).await;
+            assert!(matches!(res, Err(Error::Timeout(_))));
+            drop(socket);
+        });
+    }
+}